@@ -0,0 +1,215 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote, ToTokens};
+use syn::{braced, parse::{Parse, ParseStream}, punctuated::Punctuated, Error, Ident, Token, Visibility};
+
+use crate::CRATE;
+
+/// A store held by the database (e.g. `ExtendedTrackDb : TrackDb`).
+struct StoreDecl {
+    /// The store trait (e.g. `ExtendedTrackDb`).
+    path: syn::Path,
+    /// The store trait of the extended store, if the store is an extension.
+    base: Option<syn::Path>,
+}
+
+impl Parse for StoreDecl {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let path = input.parse()?;
+        let base = if input.peek(Token![:]) {
+            let _: Token![:] = input.parse()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
+        Ok(StoreDecl { path, base })
+    }
+}
+
+impl StoreDecl {
+    /// Returns whether this is an extension of the given store.
+    fn extends(&self, store: &StoreDecl) -> bool {
+        self.base.as_ref().map(|base| base.to_token_stream().to_string()) == Some(store.path.to_token_stream().to_string())
+    }
+
+    /// Returns the type of the store (e.g. `ExtendedTrackDbStore`).
+    fn store_type(&self) -> syn::Path {
+        let mut path = self.path.clone();
+        let last = path.segments.last_mut().unwrap();
+        last.ident = format_ident!("{}Store", last.ident);
+        path
+    }
+
+    /// Returns the name of the database field that holds the store (e.g. `extended_track_db`).
+    fn field(&self) -> Ident {
+        let name = self.path.segments.last().unwrap().ident.to_string();
+        let mut field = String::new();
+        for (i, c) in name.chars().enumerate() {
+            if c.is_uppercase() {
+                if i > 0 {
+                    field.push('_');
+                }
+                field.extend(c.to_lowercase());
+            } else {
+                field.push(c);
+            }
+        }
+        format_ident!("{}", field)
+    }
+}
+
+/// Definition of a database.
+///
+/// # Example:
+/// ```ignore
+/// pub AppDb {
+///     TrackDb,
+///     ExtendedTrackDb : TrackDb,  // an extension of `TrackDb`
+/// }
+/// ```
+struct Database {
+    attrs: Vec<syn::Attribute>,
+    vis: Visibility,
+    name: Ident,
+    stores: Punctuated<StoreDecl, Token![,]>,
+}
+
+impl Parse for Database {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let attrs = input.call(syn::Attribute::parse_outer)?;
+        let vis = input.parse()?;
+        let name = input.parse()?;
+        let content;
+        braced!(content in input);
+        let stores = Punctuated::parse_terminated(&content)?;
+        Ok(Database {
+            attrs,
+            vis,
+            name,
+            stores,
+        })
+    }
+}
+
+pub(crate) fn generate_database(input: proc_macro::TokenStream) -> syn::Result<TokenStream> {
+    let db: Database = syn::parse(input)?;
+    let name = &db.name;
+    let vis = &db.vis;
+    let attrs = &db.attrs;
+
+    for decl in db.stores.iter() {
+        if let Some(base) = &decl.base {
+            if !db.stores.iter().any(|store| decl.extends(store)) {
+                return Err(Error::new_spanned(base, "the extended store must be held by the database"));
+            }
+        }
+    }
+
+    let fields: Vec<_> = db.stores.iter().map(StoreDecl::field).collect();
+    let restores = fields.iter().map(|field| quote!(#CRATE::Store::restore(&mut self.#field, &snapshot.#field);));
+    let store_types: Vec<_> = db.stores.iter().map(StoreDecl::store_type).collect();
+
+    // `HasStore` impls, with the hooks that forward removals to the extensions of the store
+    let mut impls = vec![];
+    for decl in db.stores.iter() {
+        let field = decl.field();
+        let store_ty = decl.store_type();
+
+        let mut before_remove = vec![];
+        let mut after_remove = vec![];
+        for ext in db.stores.iter().filter(|ext| ext.extends(decl)) {
+            let ext_field = ext.field();
+            let ext_store_ty = ext.store_type();
+            // removals in the extension are in turn forwarded to the extensions of the extension
+            before_remove.push(quote! {
+                let ext_removing = #CRATE::StoreExtension::<#store_ty>::before_base_remove(&self.#ext_field, removing)?;
+                #CRATE::HasStore::<#ext_store_ty>::before_remove(self, &ext_removing)?;
+            });
+            after_remove.push(quote! {
+                let ext_removed = #CRATE::StoreExtension::<#store_ty>::after_base_remove(&mut self.#ext_field, removed);
+                #CRATE::HasStore::<#ext_store_ty>::after_remove(self, &ext_removed);
+            });
+        }
+
+        let hooks = if before_remove.is_empty() {
+            quote!()
+        } else {
+            quote! {
+                fn before_remove(&self, removing: &<#store_ty as #CRATE::Store>::Removal) -> Result<(), #CRATE::Error> {
+                    #(#before_remove)*
+                    Ok(())
+                }
+
+                fn after_remove(&mut self, removed: &<#store_ty as #CRATE::Store>::Removal) {
+                    #(#after_remove)*
+                }
+            }
+        };
+
+        impls.push(quote! {
+            impl #CRATE::HasStore<#store_ty> for #name {
+                fn store(&self) -> &#store_ty {
+                    &self.#field
+                }
+
+                fn store_mut(&mut self) -> &mut #store_ty {
+                    &mut self.#field
+                }
+
+                #hooks
+            }
+        });
+    }
+
+    Ok(quote! {
+        #(#attrs)*
+        #[derive(Clone, Default)]
+        #vis struct #name {
+            #(#fields: #store_types,)*
+            /// Saved contents of the database, by revision.
+            history: Vec<#name>,
+        }
+
+        impl #name {
+            #vis fn new() -> #name {
+                Self::default()
+            }
+
+            /// Returns the contents of the database saved at the given revision.
+            ///
+            /// Returns `None` if the revision has been discarded by a rollback, or was not taken on this database.
+            #vis fn at(&self, index: #CRATE::db::RevIndex) -> Option<&#name> {
+                self.history.get(index.revision() as usize)
+            }
+
+            /// Returns the changes to the entities of type `E` since the given revision.
+            #vis fn delta<'a, E: #CRATE::Entity>(&'a self, index: #CRATE::db::RevIndex) -> Result<impl Iterator<Item = #CRATE::Delta<&'a E>> + 'a, #CRATE::Error>
+            where
+                Self: #CRATE::HasStore<E::Store>,
+            {
+                let prev = self.at(index).ok_or(#CRATE::Error::RevisionNotFound)?;
+                let prev = #CRATE::HasStore::<E::Store>::store(prev);
+                Ok(#CRATE::EntityStore::<E>::delta(#CRATE::HasStore::<E::Store>::store(self), prev))
+            }
+        }
+
+        impl #CRATE::Database for #name {
+            fn snapshot(&mut self) -> #CRATE::db::RevIndex {
+                let snapshot = #name {
+                    #(#fields: self.#fields.clone(),)*
+                    history: Vec::new(),
+                };
+                self.history.push(snapshot);
+                #CRATE::db::RevIndex::new(self.history.len() as u32 - 1)
+            }
+
+            fn rollback(&mut self, index: #CRATE::db::RevIndex) -> Result<(), #CRATE::Error> {
+                let snapshot = self.history.get(index.revision() as usize).ok_or(#CRATE::Error::RevisionNotFound)?;
+                #(#restores)*
+                self.history.truncate(index.revision() as usize + 1);
+                Ok(())
+            }
+        }
+
+        #(#impls)*
+    })
+}
@@ -1,5 +1,6 @@
 #![recursion_limit = "256"]
 
+mod database;
 mod store;
 
 //#![feature(proc_macro_diagnostic)]
@@ -41,3 +42,22 @@ fn try_generate(
 pub fn store(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     try_generate(input, store::generate_store)
 }
+
+/// Implements a database type that holds the specified stores.
+///
+/// Extensions of a store are declared with `Name : Base`, so that removals in the base store are forwarded to them.
+///
+/// # Example
+///
+/// ```ignore
+/// database! {
+///     pub AppDb {
+///         TrackDb,
+///         ExtendedTrackDb : TrackDb,
+///     }
+/// }
+/// ```
+#[proc_macro]
+pub fn database(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    try_generate(input, database::generate_database)
+}
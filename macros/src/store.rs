@@ -1,7 +1,6 @@
-use std::collections::HashSet;
-use proc_macro2::TokenStream;
+use proc_macro2::{Delimiter, Span, TokenStream, TokenTree};
 use quote::{format_ident, quote, ToTokens, TokenStreamExt};
-use syn::{parenthesized, parse::{Parse, ParseStream}, punctuated::Punctuated, spanned::Spanned, token::Token, Error, Ident, Token, Visibility, braced};
+use syn::{parenthesized, parse::{Parse, ParseStream}, punctuated::Punctuated, spanned::Spanned, Error, Ident, Token, Visibility, braced, bracketed};
 
 use crate::store::Multiplicity::{Many, One, ZeroOrOne};
 use crate::CRATE;
@@ -9,6 +8,12 @@ use crate::CRATE;
 mod kw {
     syn::custom_keyword!(rel);
     syn::custom_keyword!(store);
+    syn::custom_keyword!(unique);
+    syn::custom_keyword!(on);
+    syn::custom_keyword!(delete);
+    syn::custom_keyword!(cascade);
+    syn::custom_keyword!(nullify);
+    syn::custom_keyword!(deny);
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
@@ -35,33 +40,236 @@ impl Parse for Multiplicity {
     }
 }
 
+/// Removes the attribute with the given name (e.g. `cluster` for `#[cluster(album, id)]`) from a list of attributes.
+fn take_attribute(attrs: &mut Vec<syn::Attribute>, name: &str) -> Option<syn::Attribute> {
+    let i = attrs.iter().position(|attr| attr.path().is_ident(name))?;
+    Some(attrs.remove(i))
+}
+
+/// Attributes that can be forwarded to the trait generated for an extension.
+const TRAIT_ATTRIBUTES: &[&str] = &["doc", "allow", "expect", "warn", "deny", "forbid"];
+
+/// Traits derived by all generated key types.
+const KEY_DERIVES: &[&str] = &["Copy", "Clone", "Eq", "PartialEq", "Ord", "PartialOrd", "Hash", "Debug"];
+
+/// Returns the attributes to forward to a generated type that already derives the given traits.
+///
+/// Those traits are removed from `#[derive(...)]` attributes, so that e.g. `#[derive(Clone, Debug)]` on an entity
+/// doesn't derive `Clone` twice on its row.
+fn forward_attrs(attrs: &[syn::Attribute], derived: &[&str]) -> syn::Result<Vec<syn::Attribute>> {
+    let mut forwarded = vec![];
+    for attr in attrs {
+        if !attr.path().is_ident("derive") {
+            forwarded.push(attr.clone());
+            continue;
+        }
+        let paths = attr.parse_args_with(Punctuated::<syn::Path, Token![,]>::parse_terminated)?;
+        let paths: Vec<_> = paths
+            .into_iter()
+            .filter(|path| !derived.iter().any(|d| path.segments.last().is_some_and(|s| s.ident == *d)))
+            .collect();
+        if !paths.is_empty() {
+            forwarded.push(syn::parse_quote!(#[derive(#(#paths),*)]));
+        }
+    }
+    Ok(forwarded)
+}
+
+/// A check constraint (e.g. `#[check(|album| album.year >= 1900)]`).
+///
+/// On entities, the constraint can be named: `#[check(valid_year, |album| album.year >= 1900)]`.
+#[derive(Clone)]
+struct Check {
+    name: Option<Ident>,
+    /// A predicate on a reference to the entity or attribute value, such as a closure or function path.
+    predicate: syn::Expr,
+}
+
+impl Parse for Check {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = if input.peek(Ident) && input.peek2(Token![,]) {
+            let name = input.parse()?;
+            let _: Token![,] = input.parse()?;
+            Some(name)
+        } else {
+            None
+        };
+        let predicate = input.parse()?;
+        Ok(Check { name, predicate })
+    }
+}
+
+impl Check {
+    /// Parses all `#[check]` attributes from a list of attributes.
+    fn take_all(attrs: &mut Vec<syn::Attribute>) -> syn::Result<Vec<Check>> {
+        let mut checks = vec![];
+        while let Some(attr) = take_attribute(attrs, "check") {
+            checks.push(attr.parse_args()?);
+        }
+        Ok(checks)
+    }
+
+    /// Returns statements that check the constraint on `value`, of type `ty`.
+    fn generate(&self, constraint: String, ty: TokenStream, value: TokenStream) -> TokenStream {
+        let predicate = &self.predicate;
+        quote! {
+            if !#CRATE::db::check_constraint::<#ty>(#value, #predicate) {
+                return Err(#CRATE::Error::CheckViolation { constraint: #constraint });
+            }
+        }
+    }
+}
+
 /// An attribute in an entity definition (e.g. `name: String`).
+#[derive(Clone)]
 struct Attr {
     attrs: Vec<syn::Attribute>,
+    /// The visibility of the getter and setter (`pub(crate) name: String`), the visibility of the store if not
+    /// specified.
+    vis: Visibility,
     name: syn::Ident,
     ty: syn::Type,
+    /// Whether the attribute has an index (`#[index]`).
+    indexed: bool,
+    /// Whether the value of the attribute must be unique among all entities (`#[unique]`).
+    unique: bool,
+    /// The default value in initializers (`#[default = expr]`, or `#[default]` for `Default::default()`).
+    default: Option<syn::Expr>,
+    /// Constraints on the value (`#[check(|year| *year >= 1900)]`).
+    checks: Vec<Check>,
+    /// Whether the attribute is optional (`comment: String?`).
+    optional: bool,
+    /// Whether the value is fixed when the entity is inserted (`#[readonly]`): no setter is generated.
+    readonly: bool,
+    /// The visibility of the setter, if different from the visibility of the getter (`#[set(pub(crate))]`).
+    set_vis: Option<Visibility>,
+}
+
+impl Attr {
+    /// Returns the visibility of the getter.
+    fn vis<'a>(&'a self, store: &'a Store) -> &'a Visibility {
+        match self.vis {
+            Visibility::Inherited => &store.vis,
+            ref vis => vis,
+        }
+    }
+
+    /// Returns the visibility of the setter.
+    fn set_vis<'a>(&'a self, store: &'a Store) -> &'a Visibility {
+        self.set_vis.as_ref().unwrap_or_else(|| self.vis(store))
+    }
+
+    /// Returns the type of the field in the row (`Option<T>` for optional attributes).
+    fn field_ty(&self) -> syn::Type {
+        let ty = &self.ty;
+        if self.optional {
+            syn::parse_quote!(Option<#ty>)
+        } else {
+            ty.clone()
+        }
+    }
+
+    /// Returns the name of the index of the attribute.
+    ///
+    /// The index maps `(value, id)` pairs for `#[index]`, and values to ids for `#[unique]`.
+    fn index_field(&self, entity: &Entity) -> Ident {
+        format_ident!("index_{}_{}", entity.name, self.name)
+    }
+
+    /// Returns statements that check the constraints of the attribute on `value`, a reference to a value.
+    ///
+    /// For optional attributes, `value` is a reference to an `Option`, and the constraints only apply to values that
+    /// are present.
+    fn generate_checks(&self, entity: &Entity, value: TokenStream) -> TokenStream {
+        let ty = &self.ty;
+        let mut checks = TokenStream::new();
+        for check in self.checks.iter() {
+            let constraint = format!("{}.{}", entity.name, self.name);
+            checks.append_all(check.generate(constraint, quote!(#ty), quote!(value)));
+        }
+        if checks.is_empty() {
+            quote!()
+        } else if self.optional {
+            quote! {
+                if let Some(value) = #value {
+                    #checks
+                }
+            }
+        } else {
+            quote! {
+                {
+                    let value = #value;
+                    #checks
+                }
+            }
+        }
+    }
+
+    /// Returns an expression that creates the error for a violation of the unique constraint of the attribute.
+    fn unique_violation(&self, entity: &Entity) -> TokenStream {
+        let entity = entity.name.to_string();
+        let attribute = self.name.to_string();
+        quote!(#CRATE::Error::UniqueViolation { entity: #entity, attribute: #attribute })
+    }
 }
 
 impl Parse for Attr {
     fn parse(input: ParseStream) -> syn::Result<Self> {
+        let vis = input.parse()?;
         let name = input.parse()?;
         let _: Token![:] = input.parse()?;
         let ty = input.parse()?;
+        let optional = if input.peek(Token![?]) {
+            let _: Token![?] = input.parse()?;
+            true
+        } else {
+            false
+        };
         Ok(Attr {
             attrs: vec![],
+            vis,
             name,
             ty,
+            indexed: false,
+            unique: false,
+            default: None,
+            checks: vec![],
+            optional,
+            readonly: false,
+            set_vis: None,
         })
     }
 }
 
+/// What happens to the sources of a relationship when its destination is deleted.
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 enum DeleteRule {
+    // `on delete deny`: the deletion fails if the destination is referenced
     Deny,
+    // `on delete nullify`: the reference is removed
     Nullify,
+    // `on delete cascade`: the sources are deleted as well
     Cascade,
 }
 
+impl Parse for DeleteRule {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let lookahead = input.lookahead1();
+        if lookahead.peek(kw::deny) {
+            let _: kw::deny = input.parse()?;
+            Ok(DeleteRule::Deny)
+        } else if lookahead.peek(kw::nullify) {
+            let _: kw::nullify = input.parse()?;
+            Ok(DeleteRule::Nullify)
+        } else if lookahead.peek(kw::cascade) {
+            let _: kw::cascade = input.parse()?;
+            Ok(DeleteRule::Cascade)
+        } else {
+            Err(lookahead.error())
+        }
+    }
+}
+
 /// A relationship in an entity definition (e.g. `rel album: Album?.tracks`).
 struct Rel {
     attrs: Vec<syn::Attribute>,
@@ -75,7 +283,10 @@ struct Rel {
     inverse: Option<Ident>,
     /// Delete rule
     delete_rule: Option<DeleteRule>,
+    /// Whether there can be at most one source for each destination (`unique`).
     unique: bool,
+    /// Whether the destinations of a to-many relationship are ordered, with duplicates allowed (`Track[]`).
+    ordered: bool,
 }
 
 impl Parse for Rel {
@@ -84,21 +295,47 @@ impl Parse for Rel {
         let name = input.parse()?;
         let _: Token![:] = input.parse()?;
         let destination = input.parse()?;
-        let multiplicity = input.parse()?;
+        let (multiplicity, ordered) = if input.peek(syn::token::Bracket) {
+            let content;
+            bracketed!(content in input);
+            if !content.is_empty() {
+                return Err(content.error("expected `[]`"));
+            }
+            (Many, true)
+        } else {
+            (input.parse()?, false)
+        };
         let inverse = if input.peek(Token![.]) {
             let _: Token![.] = input.parse()?;
             Some(input.parse()?)
         } else {
             None
         };
+        let unique = if input.peek(kw::unique) {
+            let unique: kw::unique = input.parse()?;
+            if ordered {
+                return Err(Error::new(unique.span, "ordered relationships cannot be unique"));
+            }
+            true
+        } else {
+            false
+        };
+        let delete_rule = if input.peek(kw::on) {
+            let _: kw::on = input.parse()?;
+            let _: kw::delete = input.parse()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
         Ok(Rel {
             attrs: vec![],
             name,
             destination,
             multiplicity,
             inverse,
-            delete_rule: None,
-            unique: false,
+            delete_rule,
+            unique,
+            ordered,
         })
     }
 }
@@ -120,60 +357,275 @@ impl Rel {
         })
     }
 
+    /// Returns the delete rule of the relationship.
+    ///
+    /// If not specified, deleting the destination of a mandatory relationship is denied, and optional or to-many
+    /// relationships are nullified.
+    fn delete_rule(&self) -> DeleteRule {
+        self.delete_rule.unwrap_or(match self.multiplicity {
+            One => DeleteRule::Deny,
+            ZeroOrOne | Many => DeleteRule::Nullify,
+        })
+    }
+
+    /// Returns whether the relationship is stored in the store, either as a foreign key, in junction tables or in
+    /// ordered lists.
+    fn is_stored(&self, store: &Store) -> bool {
+        !self.is_foreign_key_inverse(store) && !self.is_list_inverse(store)
+    }
+
+    /// Returns whether this relationship is the inverse side of an ordered relationship declared in the destination
+    /// entity (e.g. `rel playlists: Playlist*.tracks` in `Track`, when `Playlist` declares `rel tracks: Track[]`).
+    ///
+    /// Those relationships are not stored: they are navigated with the index of the ordered lists.
+    fn is_list_inverse(&self, store: &Store) -> bool {
+        !self.ordered && self.inverse(store).is_some_and(|inv| inv.ordered)
+    }
+
+    /// Returns whether this relationship is the inverse side of a foreign key declared in the destination entity
+    /// (e.g. `rel tracks: Track*.album` in `Album`, when `Track` declares `rel album: Album.tracks`).
+    ///
+    /// Those relationships are not stored: they are navigated with the index of the foreign key.
+    /// This is also the case for the optional side of a one-to-one relationship, when the other side is `unique`.
+    fn is_foreign_key_inverse(&self, store: &Store) -> bool {
+        match self.inverse(store) {
+            Some(inv) if inv.multiplicity != Many => match self.multiplicity {
+                Many => true,
+                One | ZeroOrOne => inv.unique && !self.unique,
+            },
+            _ => false,
+        }
+    }
+
+    /// Returns an expression that iterates over the sources of the relationship that point to the given destination.
+    ///
+    /// `entity` is the source entity of the relationship, `store_expr` is an expression of the store type.
+    fn sources_of(&self, entity: &Entity, store: &Store, store_expr: TokenStream, dst: TokenStream) -> TokenStream {
+        let src = entity.key_ty();
+        if self.ordered {
+            let list = self.list_field(entity);
+            quote!(#store_expr.#list.sources(&#dst).copied())
+        } else if self.multiplicity == Many {
+            let (_, bwd) = self.junction_fields(entity, store);
+            quote!(#store_expr.#bwd.range((#dst, <#src>::MIN)..=(#dst, <#src>::MAX)).map(|((_, src), _)| *src))
+        } else if self.unique {
+            let index = self.index_field(entity);
+            quote!(#store_expr.#index.get(&#dst).copied().into_iter())
+        } else {
+            let index = self.index_field(entity);
+            quote!(#store_expr.#index.range((#dst, <#src>::MIN)..=(#dst, <#src>::MAX)).map(|((_, src), _)| *src))
+        }
+    }
+
+    /// Returns whether this is the parent relationship of a hierarchy of entities (e.g. `rel parent: Node?.children`
+    /// in `Node`): an optional, non-unique foreign key to the entity itself.
+    fn is_hierarchy(&self, entity: &Entity) -> bool {
+        self.multiplicity == ZeroOrOne && !self.unique && self.destination == entity.name && entity.extends.is_none()
+    }
+
     /// Returns the index name for the relationship
     fn index_field(&self, entity: &Entity) -> Ident {
         format_ident!("index_{}_{}", entity.name, self.name)
     }
 
-    fn foreign_key_type(&self) -> syn::Type {
+    /// Returns the name of the store field that holds the lists of an ordered relationship.
+    fn list_field(&self, entity: &Entity) -> Ident {
+        format_ident!("list_{}_{}", entity.name, self.name)
+    }
+
+    /// Returns the key type of the destination entity.
+    fn destination_key(&self, store: &Store) -> Result<syn::Type, Error> {
+        match store.entity_by_name(&self.destination) {
+            Ok(entity) => Ok(entity.key_ty()),
+            Err(_) if store.base.is_some() => {
+                // an entity of the base store
+                let dst = &self.destination;
+                Ok(syn::parse_quote!(<#dst as #CRATE::Entity>::Id))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn foreign_key_type(&self, store: &Store) -> Result<syn::Type, Error> {
+        let ty = self.destination_key(store)?;
         if self.is_optional_one() {
-            let ty = &self.destination;
-            syn::parse_quote!(Option<#ty>)
+            Ok(syn::parse_quote!(Option<#ty>))
+        } else {
+            Ok(ty)
+        }
+    }
+
+    /// Returns whether this is a to-many relationship stored in junction tables (i.e. a many-to-many relationship).
+    ///
+    /// A to-many relationship whose inverse is a to-one relationship is not stored: it's the other side of a foreign key.
+    fn is_junction(&self, store: &Store) -> bool {
+        self.multiplicity == Many && !self.ordered && self.is_stored(store)
+    }
+
+    /// For a many-to-many relationship, returns the entity and relationship that own the junction tables,
+    /// and whether `self` is the inverse side.
+    ///
+    /// When both sides of a many-to-many relationship are declared, the one that comes first in the store owns the tables.
+    fn junction_owner<'a>(&'a self, entity: &'a Entity, store: &'a Store) -> (&'a Entity, &'a Rel, bool) {
+        if let (Some(inv), Ok(dst)) = (self.inverse(store), store.entity_by_name(&self.destination)) {
+            if store.rel_position(dst, inv) < store.rel_position(entity, self) {
+                return (dst, inv, true);
+            }
+        }
+        (entity, self, false)
+    }
+
+    /// Returns the names of the junction tables for a many-to-many relationship, as a pair `(forward, backward)`.
+    ///
+    /// The forward table maps `(source, destination)` pairs, and the backward table maps `(destination, source)` pairs.
+    fn junction_fields(&self, entity: &Entity, store: &Store) -> (Ident, Ident) {
+        let (owner, rel, inverted) = self.junction_owner(entity, store);
+        let fwd = format_ident!("multi_{}_{}", owner.name, rel.name);
+        let bwd = format_ident!("multi_{}_{}_inv", owner.name, rel.name);
+        if inverted {
+            (bwd, fwd)
         } else {
-            let ty = &self.destination;
-            syn::parse_quote!(#ty)
+            (fwd, bwd)
         }
-        // TODO many
     }
 }
 
 /// An `Attr` or `Rel` field in an entity definition.
 enum AttrOrRel {
-    Attr(Attr),
+    // boxed: attributes are much larger than relationships (types and default values)
+    Attr(Box<Attr>),
     Rel(Rel),
 }
 
 impl Parse for AttrOrRel {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let attrs = input.call(syn::Attribute::parse_outer)?;
+        let mut attrs = input.call(syn::Attribute::parse_outer)?;
         let mut item = if input.peek(kw::rel) {
             AttrOrRel::Rel(input.parse()?)
         } else {
-            AttrOrRel::Attr(input.parse()?)
+            AttrOrRel::Attr(Box::new(input.parse()?))
         };
         match item {
-            AttrOrRel::Attr(ref mut attr) => attr.attrs = attrs,
+            AttrOrRel::Attr(ref mut attr) => {
+                if let Some(index) = take_attribute(&mut attrs, "index") {
+                    index.meta.require_path_only()?;
+                    if attr.optional {
+                        return Err(Error::new_spanned(index, "optional attributes cannot be indexed"));
+                    }
+                    attr.indexed = true;
+                }
+                if let Some(unique) = take_attribute(&mut attrs, "unique") {
+                    unique.meta.require_path_only()?;
+                    if attr.indexed {
+                        return Err(Error::new_spanned(unique, "`#[unique]` attributes are already indexed"));
+                    }
+                    if attr.optional {
+                        return Err(Error::new_spanned(unique, "optional attributes cannot be unique"));
+                    }
+                    attr.unique = true;
+                }
+                attr.checks = Check::take_all(&mut attrs)?;
+                if let Some(check) = attr.checks.iter().find(|check| check.name.is_some()) {
+                    return Err(Error::new_spanned(&check.name, "constraints on attributes are named after the attribute"));
+                }
+                if let Some(default) = take_attribute(&mut attrs, "default") {
+                    attr.default = Some(match default.meta {
+                        syn::Meta::Path(_) => syn::parse_quote!(::std::default::Default::default()),
+                        syn::Meta::NameValue(meta) => meta.value,
+                        syn::Meta::List(list) => {
+                            return Err(Error::new_spanned(list, "expected `#[default]` or `#[default = value]`"));
+                        }
+                    });
+                }
+                if let Some(readonly) = take_attribute(&mut attrs, "readonly") {
+                    readonly.meta.require_path_only()?;
+                    attr.readonly = true;
+                }
+                if let Some(set) = take_attribute(&mut attrs, "set") {
+                    if attr.readonly {
+                        return Err(Error::new_spanned(set, "`#[readonly]` attributes have no setter"));
+                    }
+                    attr.set_vis = Some(set.parse_args()?);
+                }
+                attr.attrs = attrs
+            }
             AttrOrRel::Rel(ref mut rel) => rel.attrs = attrs,
         };
         Ok(item)
     }
 }
 
-/// The definition of an entity in the store.
+/// The definition of an entity in the store, or of an extension of an entity of the base store.
 ///
 /// # Example:
 /// ```ignore
 /// Album(AlbumId) {
 ///     name: String,
+///     comment: String?,          // optional attribute
+///     #[readonly]                // no setter, the value is fixed when the album is inserted
+///     created: u64,
+///     #[set(pub(crate))]         // setter only visible in the crate
+///     cover: Vec<u8>?,
+///     pub(crate) hash: u64,      // getter and setter only visible in the crate
 ///     rel tracks: Track*.album
 /// }
+///
+/// // derives and doc comments are forwarded to the row type, `#[id(...)]` attributes to the key type
+/// #[derive(Debug, PartialEq)]
+/// #[id(derive(serde::Serialize))]
+/// Artist(ArtistId) {
+///     name: String,
+/// }
+///
+/// // rows stored grouped by album
+/// #[cluster(album, id)]
+/// Track(TrackId) {
+///     rel album: Album.tracks
+/// }
+///
+/// // ordered to-many relationship, where a track can appear several times
+/// Playlist(PlaylistId) {
+///     rel tracks: Track[]
+/// }
+///
+/// // composite key, chosen when inserting the entity; the playlist must exist, and removing it removes its entries
+/// PlaylistEntry(PlaylistId, Position) {
+///     rel track: Track
+/// }
+///
+/// // abstract entity: its entities are those of its subtypes, which inherit its attributes
+/// abstract Shape(ShapeId) {
+///     name: String,
+/// }
+/// Circle(CircleId) : Shape {
+///     radius: f64,
+/// }
+///
+/// // in a store that extends the store of `Album`
+/// AlbumExt[Album] {
+///     rel studio: RecordingStudio?.albums
+/// }
 /// ```
 struct Entity {
-    /// Attributes.
+    /// Attributes, forwarded to the row type.
     attrs: Vec<syn::Attribute>,
+    /// Attributes of the key type (`#[id(derive(serde::Serialize))]`).
+    id_attrs: Vec<syn::Attribute>,
     /// The name of the entity.
     name: Ident,
+    /// The key type, or the components of a composite key.
     keys: Punctuated<Ident, Token![,]>,
+    /// For an extension, the extended entity of the base store.
+    extends: Option<Ident>,
+    /// Whether the entity is abstract (`abstract Shape(ShapeId)`): it has no table, its entities are those of its
+    /// subtypes.
+    is_abstract: bool,
+    /// The abstract entity that this entity is a subtype of (`Circle(CircleId) : Shape`).
+    supertype: Option<Ident>,
+    /// The components of the cluster key, if rows are stored in the order of other fields (`#[cluster(album, id)]`).
+    cluster: Option<Punctuated<Ident, Token![,]>>,
+    /// Constraints on the rows (`#[check(|album| album.year >= 1900)]`).
+    checks: Vec<Check>,
     /// Attributes and relationships.
     items: Punctuated<AttrOrRel, Token![,]>,
 }
@@ -182,11 +634,17 @@ impl Entity {
     /// Returns an iterator over the attributes of the entity
     fn attrs(&self) -> impl Iterator<Item = &Attr> {
         self.items.iter().filter_map(|item| match item {
-            AttrOrRel::Attr(attr) => Some(attr),
+            AttrOrRel::Attr(attr) => Some(&**attr),
             _ => None,
         })
     }
 
+    /// Returns whether the entity has modifiable attributes without an index, whose modifications by `update` are
+    /// recorded as a single change of the row (e.g. `Track_Updated`).
+    fn has_unindexed_attrs(&self) -> bool {
+        self.attrs().any(|attr| !attr.unique && !attr.indexed && !attr.readonly)
+    }
+
     /// Returns an iterator over the relationships of the entity
     fn rels(&self) -> impl Iterator<Item = &Rel> {
         self.items.iter().filter_map(|item| match item {
@@ -195,6 +653,12 @@ impl Entity {
         })
     }
 
+    /// Returns an iterator over the to-one relationships of the entity that are stored as foreign keys.
+    fn foreign_keys<'a>(&'a self, store: &'a Store) -> impl Iterator<Item = &'a Rel> + 'a {
+        self.rels()
+            .filter(move |r| r.multiplicity != Many && r.is_stored(store))
+    }
+
     fn rel_by_name(&self, name: &Ident) -> Result<&Rel, syn::Error> {
         self.rels().find(|r| &r.name == name).ok_or(Error::new(
             name.span(),
@@ -202,31 +666,205 @@ impl Entity {
         ))
     }
 
+    /// Returns the name of the store field that holds an attribute or foreign key of an extension.
+    fn extension_field(&self, name: &Ident) -> Ident {
+        format_ident!("ext_{}_{}", self.name, name)
+    }
+
+    /// Returns the name by which relationships refer to this entity.
+    ///
+    /// This is the name of the extended entity for extensions.
+    fn target_name(&self) -> &Ident {
+        self.extends.as_ref().unwrap_or(&self.name)
+    }
+
+    /// Returns whether the entity has a composite key (e.g. `PlaylistEntry(PlaylistId, Position)`).
+    ///
+    /// The key is then a struct of the components (`PlaylistEntryId`), chosen when inserting the entity.
+    fn has_composite_key(&self) -> bool {
+        self.extends.is_none() && self.keys.len() > 1
+    }
+
+    /// Returns an expression of the cluster key of `row`, for clustered entities.
+    fn cluster_key(&self, row: TokenStream) -> Option<TokenStream> {
+        let components = self.cluster.as_ref()?.iter().map(|c| {
+            if self.attrs().any(|attr| &attr.name == c) {
+                quote!(::std::clone::Clone::clone(&#row.#c))
+            } else {
+                // the id or a foreign key
+                quote!(#row.#c)
+            }
+        });
+        Some(quote!((#(#components,)*)))
+    }
+
+    /// Returns the type of the cluster key, for clustered entities (e.g. `(AlbumId, TrackId)`).
+    fn cluster_key_ty(&self, store: &Store) -> Result<Option<syn::Type>, Error> {
+        let Some(cluster) = &self.cluster else {
+            return Ok(None);
+        };
+        let mut tys = vec![];
+        for c in cluster.iter() {
+            if c == "id" {
+                tys.push(self.key_ty());
+            } else if let Some(attr) = self.attrs().find(|attr| &attr.name == c) {
+                tys.push(attr.field_ty());
+            } else if let Some(rel) = self.foreign_keys(store).find(|rel| &rel.name == c) {
+                tys.push(rel.foreign_key_type(store)?);
+            } else {
+                return Err(Error::new(
+                    c.span(),
+                    format!("`{}` is not the id, an attribute or a foreign key of `{}`", c, self.name),
+                ));
+            }
+        }
+        Ok(Some(syn::parse_quote!((#(#tys,)*))))
+    }
+
+    /// Returns statements that move the row `id` in the clustered storage after the field `name` was modified, if
+    /// it's a component of the cluster key.
+    ///
+    /// `store` must be in scope.
+    fn recluster(&self, name: &Ident, id: TokenStream) -> TokenStream {
+        match &self.cluster {
+            Some(cluster) if cluster.iter().any(|c| c == name) => {
+                let ent = &self.name;
+                let key = self.cluster_key(quote!(store.#ent[#id])).unwrap();
+                quote! {
+                    let key = #key;
+                    store.#ent.recluster(#id, key);
+                }
+            }
+            _ => quote!(),
+        }
+    }
+
+    /// Returns an expression that adds `row` to the table of the entity, without any check, and returns whether it was
+    /// added (`false` if an entity with the same key exists).
+    fn store_row(&self, row: TokenStream) -> TokenStream {
+        let ent = &self.name;
+        if self.has_composite_key() {
+            quote!(self.#ent.insert(#row))
+        } else if let Some(cluster_key) = self.cluster_key(row.clone()) {
+            quote!(self.#ent.insert_with_id(#cluster_key, #row))
+        } else {
+            quote!(self.#ent.insert_with_id(#row))
+        }
+    }
+
+    /// Returns statements that record the insertion or removal (`change`) of the entity `id`, with the values of
+    /// `row`, in the change log.
+    fn log_row(&self, store: &Store, row: TokenStream, change: &str) -> TokenStream {
+        let mut log = TokenStream::new();
+        for attr in self.attrs() {
+            let name = &attr.name;
+            log.append_all(store.log_value(&self.name, name, attr.optional, change, quote!(id), quote!(&#row.#name)));
+        }
+        for rel in self.foreign_keys(store) {
+            let name = &rel.name;
+            let optional = rel.is_optional_one();
+            log.append_all(store.log_value(&self.name, name, optional, change, quote!(id), quote!(&#row.#name)));
+        }
+        let log_entity = store.log_change(&self.name, None, change, quote!(id));
+        if change == "Inserted" {
+            quote!(#log_entity #log)
+        } else {
+            quote!(#log #log_entity)
+        }
+    }
+
+    /// Returns statements that check the constraints of the entity on `row`, but not those of its attributes.
+    fn generate_row_checks(&self, row: TokenStream) -> TokenStream {
+        let ent = &self.name;
+        let mut checks = TokenStream::new();
+        for check in self.checks.iter() {
+            let constraint = match &check.name {
+                Some(name) => format!("{}.{}", self.name, name),
+                None => self.name.to_string(),
+            };
+            checks.append_all(check.generate(constraint, quote!(#ent), row.clone()));
+        }
+        checks
+    }
+
     fn key_ty(&self) -> syn::Type {
-        if self.keys.len()  == 1 {
-            let k = &self.keys[0];
+        if let Some(base) = &self.extends {
+            syn::parse_quote!(<#base as #CRATE::Entity>::Id)
+        } else if self.has_composite_key() {
+            let k = format_ident!("{}Id", self.name);
             syn::parse_quote!(#k)
         } else {
-            let ks = self.keys.iter();
-            syn::parse_quote!((#(#ks),*))
+            let k = &self.keys[0];
+            syn::parse_quote!(#k)
         }
     }
 }
 
 impl Parse for Entity {
     fn parse(input: ParseStream) -> syn::Result<Self> {
-        let attrs = input.call(syn::Attribute::parse_outer)?;
+        let mut attrs = input.call(syn::Attribute::parse_outer)?;
+        let is_abstract = if input.peek(Token![abstract]) {
+            let _: Token![abstract] = input.parse()?;
+            true
+        } else {
+            false
+        };
         let name = input.parse()?;
 
+        let cluster = match take_attribute(&mut attrs, "cluster") {
+            Some(attr) => Some(attr.parse_args_with(Punctuated::parse_terminated)?),
+            None => None,
+        };
+        let checks = Check::take_all(&mut attrs)?;
+        let mut id_attrs = vec![];
+        while let Some(attr) = take_attribute(&mut attrs, "id") {
+            let metas = attr.parse_args_with(Punctuated::<syn::Meta, Token![,]>::parse_terminated)?;
+            id_attrs.extend(metas.into_iter().map(|meta| -> syn::Attribute { syn::parse_quote!(#[#meta]) }));
+        }
+
         let content;
-        parenthesized!(content in input);
-        let keys = Punctuated::parse_terminated(&content)?;
+        let mut keys = Punctuated::new();
+        let mut extends = None;
+        if input.peek(syn::token::Bracket) {
+            // extension: `AlbumExt[Album]`
+            bracketed!(content in input);
+            extends = Some(content.parse()?);
+        } else {
+            parenthesized!(content in input);
+            keys = Punctuated::parse_terminated(&content)?;
+        }
+
+        let supertype = if input.peek(Token![:]) {
+            let _: Token![:] = input.parse()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
 
         let content;
         braced!(content in input);
-        let items = Punctuated::parse_terminated(&content)?;
+        let mut items: Punctuated<AttrOrRel, Token![,]> = Punctuated::parse_terminated(&content)?;
+        if extends.is_some() {
+            // existing entities of the base store don't have a value
+            for item in items.iter_mut() {
+                if let AttrOrRel::Attr(attr) = item {
+                    attr.optional = true;
+                }
+            }
+        }
 
-        Ok(Entity { attrs, keys, name, items })
+        Ok(Entity {
+            attrs,
+            id_attrs,
+            keys,
+            name,
+            extends,
+            is_abstract,
+            supertype,
+            cluster,
+            checks,
+            items,
+        })
     }
 }
 
@@ -240,12 +878,22 @@ impl Parse for Entity {
 ///    rel tracks: Track*.album   // one side of a relationship: one-to-many
 /// );
 /// ```
+///
+/// A store can extend the entities of another store (`pub store ExtendedTrackDb : TrackDb;`).
+///
+/// A store declared with a version (`#[version(2)] pub store TrackDb;`) can export its data and import data of the
+/// same or an earlier version, upgraded by migrations.
 struct Store {
     attrs: Vec<syn::Attribute>,
     /// Optional visibility.
     vis: Visibility,
     /// The name of the store. Declared with `store Name;`.
     name: Ident,
+    /// The store trait of the extended store, if any (`store Name : Base;`).
+    base: Option<syn::Path>,
+    /// The version of the schema (`#[version(2)]`): versioned stores can export and import their data, and upgrade
+    /// data of earlier versions.
+    version: Option<(u32, Span)>,
     /// Entity definitions.
     entities: Vec<Entity>,
 }
@@ -253,10 +901,23 @@ struct Store {
 impl Parse for Store {
     fn parse(input: ParseStream) -> syn::Result<Self> {
         // Parse the `store Name;` directive.
-        let attrs = input.call(syn::Attribute::parse_outer)?;
+        let mut attrs = input.call(syn::Attribute::parse_outer)?;
+        let version = match take_attribute(&mut attrs, "version") {
+            Some(attr) => {
+                let version: syn::LitInt = attr.parse_args()?;
+                Some((version.base10_parse()?, version.span()))
+            }
+            None => None,
+        };
         let vis = input.parse()?;
         let _: kw::store = input.parse()?;
         let name = input.parse()?;
+        let base = if input.peek(Token![:]) {
+            let _: Token![:] = input.parse()?;
+            Some(input.parse()?)
+        } else {
+            None
+        };
         let _: Token![;] = input.parse()?;
 
         // Parse the entity definitions.
@@ -268,6 +929,8 @@ impl Parse for Store {
             attrs,
             vis,
             name,
+            base,
+            version,
             entities,
         })
     }
@@ -277,131 +940,867 @@ impl Store {
     fn entity_by_name(&self, name: &Ident) -> Result<&Entity, syn::Error> {
         self.entities
             .iter()
-            .find(|e| &e.name == name)
+            .find(|e| e.target_name() == name)
             .ok_or(Error::new(
                 name.span(),
                 format!("entity `{}` not found", name),
             ))
     }
 
+    /// Returns the position of a relationship in the store, in declaration order.
+    fn rel_position(&self, entity: &Entity, rel: &Rel) -> (usize, usize) {
+        let e = self.entities.iter().position(|e| e.name == entity.name).unwrap();
+        let r = entity.rels().position(|r| r.name == rel.name).unwrap();
+        (e, r)
+    }
+
+    /// Returns the entities that are stored in tables of this store (i.e. not extensions or abstract entities).
+    fn tables(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.iter().filter(|e| e.extends.is_none() && !e.is_abstract)
+    }
+
+    /// Returns the extensions of entities of the base store.
+    fn extensions(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.iter().filter(|e| e.extends.is_some())
+    }
+
+    /// Returns the abstract entities.
+    fn abstracts(&self) -> impl Iterator<Item = &Entity> {
+        self.entities.iter().filter(|e| e.is_abstract)
+    }
+
+    /// Returns the subtypes of an abstract entity.
+    fn subtypes<'a>(&'a self, entity: &'a Ident) -> impl Iterator<Item = &'a Entity> + 'a {
+        self.tables().filter(move |e| e.supertype.as_ref() == Some(entity))
+    }
+
+    /// Returns whether the entity with the given name is declared in this store, as a table or an abstract entity
+    /// (i.e. not an entity of the base store).
+    fn is_local(&self, name: &Ident) -> bool {
+        self.entities.iter().any(|e| e.extends.is_none() && &e.name == name)
+    }
+
+    /// Returns whether the entity with the given name is abstract.
+    fn is_abstract(&self, name: &Ident) -> bool {
+        self.abstracts().any(|e| &e.name == name)
+    }
+
+    /// Returns the names of the entities of the base store that are extended or referenced by this store.
+    fn base_entities(&self) -> Vec<&Ident> {
+        let mut names: Vec<&Ident> = vec![];
+        let extended = self.extensions().map(|e| e.target_name());
+        let referenced = self.entities.iter().flat_map(|e| e.rels()).map(|r| &r.destination);
+        for name in extended.chain(referenced) {
+            if !self.is_local(name) && !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        names
+    }
+
     fn store_type(&self) -> syn::Type {
         let name = &self.name;
         let ty = format_ident!("{}Store", name);
         syn::parse_quote!(#ty)
     }
 
-    /// Returns all indices on the given entity.
-    fn indices_for_entity(&self, entity: &Entity) -> Vec<Ident> {
-        let mut indices = HashSet::new();
-        for ent in self.entities.iter() {
-            for rel in ent.rels() {
-                if rel.destination == entity.name {
-                    indices.insert(rel.index_field(entity));
+    /// Returns the path to a type generated for the base store (e.g. `TrackDbStore` for the suffix `Store`).
+    fn base_type(&self, suffix: &str) -> Option<syn::Path> {
+        let mut path = self.base.clone()?;
+        let last = path.segments.last_mut().unwrap();
+        last.ident = format_ident!("{}{}", last.ident, suffix);
+        Some(path)
+    }
+
+    /// Returns an expression that checks whether an entity of this store or of the base store exists.
+    ///
+    /// `db` is an expression of the database type.
+    fn contains(&self, entity: &Ident, db: TokenStream, id: TokenStream) -> TokenStream {
+        if self.is_local(entity) {
+            let store_ty = self.store_type();
+            self.contains_in(entity, quote!(#CRATE::HasStore::<#store_ty>::store(#db)), id)
+        } else {
+            let base_store_ty = self.base_type("Store");
+            quote!(#CRATE::EntityStore::<#entity>::contains(#CRATE::HasStore::<#base_store_ty>::store(#db), #id))
+        }
+    }
+
+    /// Returns an expression that checks whether an entity of this store exists.
+    ///
+    /// `store_expr` is an expression of the store type.
+    fn contains_in(&self, entity: &Ident, store_expr: TokenStream, id: TokenStream) -> TokenStream {
+        if self.is_abstract(entity) {
+            quote!(#id.exists_in(#store_expr))
+        } else {
+            quote!(#store_expr.#entity.contains(#id))
+        }
+    }
+
+    /// Returns the type of the set of entities removed by a removal operation (e.g. `TrackDbRemoval`).
+    fn removal_type(&self) -> Ident {
+        format_ident!("{}Removal", self.name)
+    }
+
+    /// Returns the type of the changes recorded in the change log of the store (e.g. `TrackDbChangeKind`).
+    fn change_kind(&self) -> Ident {
+        format_ident!("{}ChangeKind", self.name)
+    }
+
+    /// Returns a statement that records a change in the change log of the store, with `store` in scope.
+    ///
+    /// The change is named after the entity, the attribute or relationship if any, and `change`
+    /// (e.g. `Track_album_Inserted`).
+    fn log_change(&self, entity: &Ident, member: Option<&Ident>, change: &str, args: TokenStream) -> TokenStream {
+        let change_kind = self.change_kind();
+        let variant = match member {
+            Some(member) => format_ident!("{}_{}_{}", entity, member, change),
+            None => format_ident!("{}_{}", entity, change),
+        };
+        quote!(store.changes.push(#change_kind::#variant(#args));)
+    }
+
+    /// Returns statements that record the insertion or removal (`change`) of the value of an attribute or foreign key,
+    /// given by reference.
+    ///
+    /// Nothing is recorded for the absent value of an optional attribute or foreign key.
+    fn log_value(
+        &self,
+        entity: &Ident,
+        member: &Ident,
+        optional: bool,
+        change: &str,
+        id: TokenStream,
+        value: TokenStream,
+    ) -> TokenStream {
+        if optional {
+            let log = self.log_change(entity, Some(member), change, quote!(#id, ::std::clone::Clone::clone(value)));
+            quote! {
+                if let Some(value) = #value {
+                    #log
                 }
             }
+        } else {
+            self.log_change(entity, Some(member), change, quote!(#id, ::std::clone::Clone::clone(#value)))
         }
-        indices.into_iter().collect()
     }
 
-    /// Returns all foreign-key references to the given entity.
-    fn foreign_key_refs(&self, entity: &Entity) -> Vec<(&Entity,&Rel)> {
-        let mut refs = vec![];
+    /// Returns all many-to-many relationships that own junction tables.
+    fn junctions(&self) -> Vec<(&Entity, &Rel)> {
+        let mut junctions = vec![];
         for ent in self.entities.iter() {
             for rel in ent.rels() {
-                if rel.destination == entity.name {
-                    refs.push((ent,rel));
+                if rel.is_junction(self) && !rel.junction_owner(ent, self).2 {
+                    junctions.push((ent, rel));
                 }
             }
         }
-        refs
+        junctions
     }
-}
-
-////////////////////////////////////////////////////////////////////////////////////////////////////
-// CODEGEN
 
+    /// Returns all ordered relationships.
+    fn lists(&self) -> impl Iterator<Item = (&Entity, &Rel)> {
+        self.entities.iter().flat_map(|ent| ent.rels().filter(|r| r.ordered).map(move |rel| (ent, rel)))
+    }
 
-///
+    /// Returns the name and attributes of the method that navigates a relationship in the inverse direction.
+    ///
+    /// This is the relationship in the destination entity that is declared as the inverse of a foreign key, or
+    /// otherwise the name given with `.inverse` if the destination entity doesn't declare it.
+    fn inverse_navigation<'a>(&'a self, entity: &Entity, rel: &'a Rel) -> Option<(&'a Ident, &'a [syn::Attribute])> {
+        let dst = self.entity_by_name(&rel.destination).ok()?;
+        if rel.multiplicity != Many || rel.ordered {
+            if let Some(inv) = dst.rels().find(|r| {
+                &r.destination == entity.target_name() && r.inverse.as_ref() == Some(&rel.name) && !r.is_stored(self)
+            }) {
+                return Some((&inv.name, &inv.attrs));
+            }
+        }
+        let name = rel.inverse.as_ref()?;
+        if dst.rels().any(|r| &r.name == name) {
+            return None;
+        }
+        Some((name, &[]))
+    }
+
+    /// Returns all references to the given entity, via foreign keys or many-to-many relationships.
+    /// Returns the components of the composite key of `entity` that are keys of entities of this store, with their
+    /// position (e.g. `(0, Playlist)` for `PlaylistEntry(PlaylistId, Position)`).
+    ///
+    /// The referenced entity must exist when the entity is inserted, and removing it removes the entity.
+    fn key_refs<'a>(&'a self, entity: &'a Entity) -> impl Iterator<Item = (usize, &'a Entity)> + 'a {
+        let components = entity.keys.iter().enumerate().filter(|_| entity.has_composite_key());
+        components.filter_map(|(i, key)| {
+            let dst = self.tables().find(|e| !e.has_composite_key() && &e.keys[0] == key)?;
+            Some((i, dst))
+        })
+    }
+
+    fn foreign_key_refs(&self, entity: &Ident) -> Vec<(&Entity,&Rel)> {
+        let mut refs = vec![];
+        for ent in self.entities.iter() {
+            for rel in ent.rels() {
+                if &rel.destination == entity && rel.is_stored(self) {
+                    refs.push((ent,rel));
+                }
+            }
+        }
+        refs
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////////////////////////
+// CODEGEN
+
+/// A method generated on the key type of an entity.
+///
+/// Methods on the key type of an entity of the base store are generated in an extension trait instead of an
+/// inherent impl.
+struct Method {
+    attrs: Vec<syn::Attribute>,
+    /// The signature of the method, without visibility.
+    sig: TokenStream,
+    body: TokenStream,
+}
+
+impl Method {
+    fn inherent(&self, vis: &Visibility) -> TokenStream {
+        let Method { attrs, sig, body } = self;
+        quote! {
+            #(#attrs)*
+            #vis #sig {
+                #body
+            }
+        }
+    }
+
+    fn trait_decl(&self) -> TokenStream {
+        let Method { attrs, sig, .. } = self;
+        quote! {
+            #(#attrs)*
+            #sig;
+        }
+    }
+
+    fn trait_impl(&self) -> TokenStream {
+        let Method { sig, body, .. } = self;
+        quote! {
+            #sig {
+                #body
+            }
+        }
+    }
+}
+
+/// Generates the getter, `add_<rel>` and `remove_<rel>` methods of a many-to-many relationship.
+fn generate_junction_methods(store: &Store, entity: &Entity, rel: &Rel) -> Result<Vec<Method>, Error> {
+    let name = &rel.name;
+    let adder = format_ident!("add_{}", rel.name);
+    let remover = format_ident!("remove_{}", rel.name);
+    let store_ty = store.store_type();
+    let db_name = &store.name;
+    let err = quote!(#CRATE::Error);
+    let dst = rel.destination_key(store)?;
+    let (fwd, bwd) = rel.junction_fields(entity, store);
+    let src_exists = store.contains(entity.target_name(), quote!(db), quote!(self));
+    let dst_exists = store.contains(&rel.destination, quote!(db), quote!(dst));
+    // changes are recorded in the direction of the relationship that owns the junction tables
+    let (owner, owner_rel, inverted) = rel.junction_owner(entity, store);
+    let pair = if inverted { quote!(dst, self) } else { quote!(self, dst) };
+    let log_inserted = store.log_change(&owner.name, Some(&owner_rel.name), "Inserted", pair.clone());
+    let log_removed = store.log_change(&owner.name, Some(&owner_rel.name), "Removed", pair);
+
+    Ok(vec![
+        Method {
+            attrs: rel.attrs.clone(),
+            sig: quote!(fn #name <DB: ?Sized + #db_name> (self, db: &DB) -> impl Iterator<Item = #dst> + '_),
+            body: quote! {
+                #CRATE::HasStore::<#store_ty>::store(db).#fwd.range((self, <#dst>::MIN)..=(self, <#dst>::MAX)).map(|((_, dst), _)| *dst)
+            },
+        },
+        Method {
+            attrs: vec![],
+            sig: quote!(fn #adder <DB: ?Sized + #db_name> (self, db: &mut DB, dst: #dst) -> Result<(),#err>),
+            body: quote! {
+                if !#src_exists {
+                    return Err(#err::EntityNotFound);
+                }
+                if !#dst_exists {
+                    return Err(#err::ForeignKeyViolation);
+                }
+                let store = #CRATE::HasStore::<#store_ty>::store_mut(db);
+                if store.#fwd.insert((self, dst), ()).is_none() {
+                    store.#bwd.insert((dst, self), ());
+                    store.changes.begin_operation();
+                    #log_inserted
+                }
+                Ok(())
+            },
+        },
+        Method {
+            attrs: vec![],
+            sig: quote!(fn #remover <DB: ?Sized + #db_name> (self, db: &mut DB, dst: #dst) -> Result<(),#err>),
+            body: quote! {
+                let store = #CRATE::HasStore::<#store_ty>::store_mut(db);
+                if store.#fwd.remove(&(self, dst)).is_some() {
+                    store.#bwd.remove(&(dst, self));
+                    store.changes.begin_operation();
+                    #log_removed
+                }
+                Ok(())
+            },
+        },
+    ])
+}
+
+/// Generates the methods of an ordered relationship: the getter, which iterates over the destinations in order,
+/// `insert_<rel>_at`, `remove_<rel>_at`, `move_<rel>` and `<rel>_delta`.
+fn generate_list_methods(store: &Store, entity: &Entity, rel: &Rel) -> Result<Vec<Method>, Error> {
+    let name = &rel.name;
+    let insert_at = format_ident!("insert_{}_at", name);
+    let remove_at = format_ident!("remove_{}_at", name);
+    let move_to = format_ident!("move_{}", name);
+    let delta = format_ident!("{}_delta", name);
+    let store_ty = store.store_type();
+    let db_name = &store.name;
+    let err = quote!(#CRATE::Error);
+    let src = entity.key_ty();
+    let dst = rel.destination_key(store)?;
+    let list = rel.list_field(entity);
+    let src_exists = store.contains(entity.target_name(), quote!(db), quote!(self));
+    let dst_exists = store.contains(&rel.destination, quote!(db), quote!(dst));
+    let get_store = |db| quote!(#CRATE::HasStore::<#store_ty>::store(#db));
+    let (store_db, store_prev) = (get_store(quote!(db)), get_store(quote!(prev)));
+    let doc = |text: &str| -> Vec<syn::Attribute> { vec![syn::parse_quote!(#[doc = #text])] };
+    let log = |change| store.log_change(&entity.name, Some(name), change, quote!(self, dst));
+    let (log_inserted, log_removed, log_moved) = (log("Inserted"), log("Removed"), log("Moved"));
+
+    Ok(vec![
+        Method {
+            attrs: rel.attrs.clone(),
+            sig: quote!(fn #name <DB: ?Sized + #db_name> (self, db: &DB) -> impl Iterator<Item = #dst> + '_),
+            body: quote!(#store_db.#list.iter(&self).copied()),
+        },
+        Method {
+            attrs: doc("Inserts a destination at `index`, before the destinations that follow."),
+            sig: quote! {
+                fn #insert_at <DB: ?Sized + #db_name> (self, db: &mut DB, index: usize, dst: #dst) -> Result<(),#err>
+            },
+            body: quote! {
+                if !#src_exists {
+                    return Err(#err::EntityNotFound);
+                }
+                if !#dst_exists {
+                    return Err(#err::ForeignKeyViolation);
+                }
+                let store = #CRATE::HasStore::<#store_ty>::store_mut(db);
+                let len = store.#list.len(&self);
+                if !store.#list.insert_at(self, index, dst) {
+                    return Err(#err::PositionOutOfBounds { index, len });
+                }
+                store.changes.begin_operation();
+                #log_inserted
+                Ok(())
+            },
+        },
+        Method {
+            attrs: doc("Removes the destination at `index`."),
+            sig: quote!(fn #remove_at <DB: ?Sized + #db_name> (self, db: &mut DB, index: usize) -> Result<#dst,#err>),
+            body: quote! {
+                let store = #CRATE::HasStore::<#store_ty>::store_mut(db);
+                let len = store.#list.len(&self);
+                let dst = store.#list.remove_at(&self, index).ok_or(#err::PositionOutOfBounds { index, len })?;
+                store.changes.begin_operation();
+                #log_removed
+                Ok(dst)
+            },
+        },
+        Method {
+            attrs: doc("Moves the destination at `from` to `to`, its index after the move."),
+            sig: quote! {
+                fn #move_to <DB: ?Sized + #db_name> (self, db: &mut DB, from: usize, to: usize) -> Result<(),#err>
+            },
+            body: quote! {
+                let store = #CRATE::HasStore::<#store_ty>::store_mut(db);
+                let len = store.#list.len(&self);
+                let Some(&dst) = store.#list.get(&self, from) else {
+                    return Err(#err::PositionOutOfBounds { index: from, len });
+                };
+                if !store.#list.move_to(&self, from, to) {
+                    return Err(#err::PositionOutOfBounds { index: to, len });
+                }
+                store.changes.begin_operation();
+                #log_moved
+                Ok(())
+            },
+        },
+        Method {
+            attrs: doc("Returns the changes to the lists of destinations since `prev`, with moves reported as such."),
+            sig: quote! {
+                fn #delta <'a, DB: ?Sized + #db_name> (db: &'a DB, prev: &'a DB)
+                    -> impl Iterator<Item = #CRATE::ListDelta<#src, #dst>> + 'a
+            },
+            body: quote!(#store_db.#list.delta(&#store_prev.#list)),
+        },
+    ])
+}
+
+/// Generates statements that remove the entity `id` from both sides of the many-to-many and ordered relationships
+/// it's part of.
+///
+/// `target` is the name by which relationships refer to the entity, and `store` must be in scope.
+fn generate_junction_cleanup(store: &Store, target: &Ident) -> Result<TokenStream, Error> {
+    let mut cleanup = TokenStream::new();
+    for (owner, rel) in store.lists() {
+        let list = rel.list_field(owner);
+        if owner.target_name() == target {
+            let log = store.log_change(&owner.name, Some(&rel.name), "Removed", quote!(id, dst));
+            cleanup.append_all(quote! {
+                let targets: Vec<_> = store.#list.iter(&id).copied().collect();
+                for dst in targets {
+                    #log
+                }
+                store.#list.remove_source(&id);
+            });
+        }
+        if &rel.destination == target {
+            let log = store.log_change(&owner.name, Some(&rel.name), "Removed", quote!(src, id));
+            cleanup.append_all(quote! {
+                let sources: Vec<_> = store.#list.sources(&id).copied().collect();
+                for src in sources {
+                    let count = store.#list.iter(&src).filter(|&&dst| dst == id).count();
+                    for _ in 0..count {
+                        #log
+                    }
+                }
+                store.#list.remove_target(&id);
+            });
+        }
+    }
+    for (owner, rel) in store.junctions() {
+        let (fwd, bwd) = rel.junction_fields(owner, store);
+        if owner.target_name() == target {
+            let dst = rel.destination_key(store)?;
+            let log = store.log_change(&owner.name, Some(&rel.name), "Removed", quote!(id, dst));
+            cleanup.append_all(quote! {
+                let targets: Vec<#dst> = store.#fwd.range((id, <#dst>::MIN)..=(id, <#dst>::MAX)).map(|((_, dst), _)| *dst).collect();
+                for dst in targets {
+                    store.#fwd.remove(&(id, dst));
+                    store.#bwd.remove(&(dst, id));
+                    #log
+                }
+            });
+        }
+        if &rel.destination == target {
+            let src = owner.key_ty();
+            let log = store.log_change(&owner.name, Some(&rel.name), "Removed", quote!(src, id));
+            cleanup.append_all(quote! {
+                let sources: Vec<#src> = store.#bwd.range((id, <#src>::MIN)..=(id, <#src>::MAX)).map(|((_, src), _)| *src).collect();
+                for src in sources {
+                    store.#fwd.remove(&(src, id));
+                    store.#bwd.remove(&(id, src));
+                    #log
+                }
+            });
+        }
+    }
+    Ok(cleanup)
+}
+
+/// Generates the method that navigates a stored relationship in the inverse direction, if there's one.
+///
+/// The method is generated on the key type of the destination.
+fn generate_inverse_getter(store: &Store, entity: &Entity, rel: &Rel) -> Result<Option<Method>, Error> {
+    let Some((inv_name, inv_attrs)) = store.inverse_navigation(entity, rel) else {
+        return Ok(None);
+    };
+    let key = entity.key_ty();
+    let store_ty = store.store_type();
+    let db_name = &store.name;
+    let get_store = quote!(#CRATE::HasStore::<#store_ty>::store(db));
+    let method = if rel.unique {
+        let index = rel.index_field(entity);
+        Method {
+            attrs: inv_attrs.to_vec(),
+            sig: quote!(fn #inv_name <DB: ?Sized + #db_name> (self, db: &DB) -> Option<#key>),
+            body: quote!(#get_store.#index.get(&self).copied()),
+        }
+    } else {
+        Method {
+            attrs: inv_attrs.to_vec(),
+            sig: quote!(fn #inv_name <DB: ?Sized + #db_name> (self, db: &DB) -> impl Iterator<Item = #key> + '_),
+            body: rel.sources_of(entity, store, get_store, quote!(self)),
+        }
+    };
+    Ok(Some(method))
+}
+
+/// Generates the navigation methods of a hierarchy of entities (`ancestors`, `root`, `depth`, `descendants` and
+/// `descendants_breadth_first`), from its parent relationship.
+///
+/// If the entity has several hierarchies, the methods are prefixed with the name of the relationship
+/// (e.g. `parent_ancestors`).
+fn generate_hierarchy_methods(store: &Store, entity: &Entity, rel: &Rel) -> Vec<Method> {
+    let ent = &entity.name;
+    let key = entity.key_ty();
+    let fk = &rel.name;
+    let store_ty = store.store_type();
+    let db_name = &store.name;
+    let get_store = quote!(#CRATE::HasStore::<#store_ty>::store(db));
+    let children = rel.sources_of(entity, store, quote!(store), quote!(id));
+
+    let prefix = if entity.rels().filter(|r| r.is_hierarchy(entity)).count() > 1 {
+        format!("{}_", fk)
+    } else {
+        String::new()
+    };
+    let ancestors = format_ident!("{}ancestors", prefix);
+    let root = format_ident!("{}root", prefix);
+    let depth = format_ident!("{}depth", prefix);
+    let descendants = format_ident!("{}descendants", prefix);
+    let descendants_breadth_first = format_ident!("{}descendants_breadth_first", prefix);
+    let doc = |text: &str| -> Vec<syn::Attribute> { vec![syn::parse_quote!(#[doc = #text])] };
+
+    vec![
+        Method {
+            attrs: doc("Returns the ancestors of the entity, from its parent to the root of the hierarchy (none if the \
+                entity doesn't exist)."),
+            sig: quote!(fn #ancestors <DB: ?Sized + #db_name> (self, db: &DB) -> impl Iterator<Item = #key> + '_),
+            body: quote! {
+                let store = #get_store;
+                let parent = store.#ent.get(self).and_then(|row| row.#fk);
+                ::std::iter::successors(parent, move |&id| store.#ent[id].#fk)
+            },
+        },
+        Method {
+            attrs: doc("Returns the root of the hierarchy that contains the entity (itself if it has no parent)."),
+            sig: quote!(fn #root <DB: ?Sized + #db_name> (self, db: &DB) -> #key),
+            body: quote!(self.#ancestors(db).last().unwrap_or(self)),
+        },
+        Method {
+            attrs: doc("Returns the number of ancestors of the entity."),
+            sig: quote!(fn #depth <DB: ?Sized + #db_name> (self, db: &DB) -> usize),
+            body: quote!(self.#ancestors(db).count()),
+        },
+        Method {
+            attrs: doc("Returns the descendants of the entity in depth-first order, each entity before its children."),
+            sig: quote!(fn #descendants <DB: ?Sized + #db_name> (self, db: &DB) -> impl Iterator<Item = #key> + '_),
+            body: quote! {
+                let store = #get_store;
+                let children = move |id: #key| {
+                    let mut children: Vec<#key> = #children.collect();
+                    children.reverse();
+                    children
+                };
+                let mut stack = children(self);
+                ::std::iter::from_fn(move || {
+                    let id = stack.pop()?;
+                    stack.extend(children(id));
+                    Some(id)
+                })
+            },
+        },
+        Method {
+            attrs: doc("Returns the descendants of the entity in breadth-first order, by increasing depth."),
+            sig: quote! {
+                fn #descendants_breadth_first <DB: ?Sized + #db_name> (self, db: &DB) -> impl Iterator<Item = #key> + '_
+            },
+            body: quote! {
+                let store = #get_store;
+                let id = self;
+                let mut queue: ::std::collections::VecDeque<#key> = #children.collect();
+                ::std::iter::from_fn(move || {
+                    let id = queue.pop_front()?;
+                    queue.extend(#children);
+                    Some(id)
+                })
+            },
+        },
+    ]
+}
+
+/// Generates the inverse navigation methods of the relationships of an entity that point to entities of this store.
+///
+/// Those that point to an entity of the base store are generated in the extension of the entity.
+fn generate_inverse_getters(store: &Store, entity: &Entity) -> Result<TokenStream, Error> {
+    let mut getters = TokenStream::new();
+    for rel in entity.rels().filter(|r| r.is_stored(store)) {
+        let Some(method) = generate_inverse_getter(store, entity, rel)? else {
+            continue;
+        };
+        if store.is_local(&rel.destination) {
+            let dst = rel.destination_key(store)?;
+            let method = method.inherent(&store.vis);
+            getters.append_all(quote! {
+                impl #dst {
+                    #method
+                }
+            });
+        } else if store.entity_by_name(&rel.destination).is_err() {
+            return Err(Error::new(
+                rel.destination.span(),
+                format!(
+                    "the inverse of `{}` is navigated from `{}`, which requires an extension of `{}` in this store",
+                    rel.name, rel.destination, rel.destination
+                ),
+            ));
+        }
+    }
+    Ok(getters)
+}
+
+/// Generates the row type of an entity (e.g. `Track`), its key type (`TrackId`) and their methods: insertion and
+/// removal, getters and setters, and the navigation of relationships.
 fn generate_entity(
     store: &Store,
     entity: &Entity,
 ) -> Result<TokenStream, Error>
 {
+    if entity.keys.is_empty() {
+        return Err(Error::new(entity.name.span(), "missing key type (e.g. `Album(AlbumId)`)"));
+    }
+    if let Some(cluster) = &entity.cluster {
+        if entity.has_composite_key() {
+            return Err(Error::new(
+                entity.name.span(),
+                "entities with composite keys are already stored in key order, they cannot be clustered",
+            ));
+        }
+        if !cluster.iter().any(|c| c == "id") {
+            return Err(Error::new(cluster.span(), "the cluster key must include `id`, to make it unique"));
+        }
+    }
     let ent = &entity.name;
     let key = entity.key_ty();
     let store_ty = store.store_type();
+    let removal_ty = store.removal_type();
     let err = quote!(#CRATE::Error);
     let vis = &store.vis;
     let db_name = &store.name;
-    let fields = entity.items.iter().map(|item| match item {
-        AttrOrRel::Attr(Attr {
-            ref name, ref ty, ..
-        }) => quote!(#name: #ty),
-        AttrOrRel::Rel(Rel {
-            ref name,
-            ref destination,
-            ref multiplicity,
-            ..
-        }) => match multiplicity {
-            ZeroOrOne => quote!(#name: Option<#destination>),
-            One => quote!(#name: #destination),
-            Many => quote!(#name: Vec<#destination>),
-        },
-    });
-
-
+    let get_store = quote!(#CRATE::HasStore::<#store_ty>::store);
+    let get_store_mut = quote!(#CRATE::HasStore::<#store_ty>::store_mut);
+    // Fields of the row, with their default values in initializers
+    let mut row_fields: Vec<(&Ident, syn::Type, Option<TokenStream>)> = vec![];
+    for item in entity.items.iter() {
+        match item {
+            AttrOrRel::Attr(attr) => {
+                let Attr { name, default, .. } = &**attr;
+                let default = match default {
+                    Some(expr) => Some(quote!(#expr)),
+                    None if attr.optional => Some(quote!(None)),
+                    None => None,
+                };
+                row_fields.push((name, attr.field_ty(), default));
+            }
+            AttrOrRel::Rel(rel @ Rel { ref name, multiplicity: One | ZeroOrOne, .. }) if rel.is_stored(store) => {
+                let ty = rel.foreign_key_type(store)?;
+                let default = rel.is_optional_one().then(|| quote!(None));
+                row_fields.push((name, ty, default));
+            }
+            // to-many relationships and inverses of foreign keys are not stored in the row
+            AttrOrRel::Rel(_) => {}
+        }
+    }
+    let fields: Vec<_> = row_fields.iter().map(|(name, ty, _)| quote!(#name: #ty)).collect();
 
     // Attribute getters
     let mut attr_getters = vec![];
     for item in entity.items.iter() {
         match item {
-            AttrOrRel::Attr(Attr { ref name, ref ty, ref attrs }) => {
+            AttrOrRel::Attr(attr) if attr.optional => {
+                let Attr { name, ty, attrs, .. } = &**attr;
+                let vis = attr.vis(store);
+                attr_getters.push(quote! {
+                    #(#attrs)*
+                    #vis fn #name <DB: ?Sized + #db_name> (self, db: &DB) -> Option<&#ty> {
+                        #get_store(db).#ent[self].#name.as_ref()
+                    }
+                });
+            }
+            AttrOrRel::Attr(attr) => {
+                let Attr { name, ty, attrs, .. } = &**attr;
+                let vis = attr.vis(store);
                 attr_getters.push(quote! {
                     #(#attrs)*
                     #vis fn #name <DB: ?Sized + #db_name> (self, db: &DB) -> &#ty {
-                        &db.store().#ent[self].#name
+                        &#get_store(db).#ent[self].#name
                     }
 
                 });
             }
-            AttrOrRel::Rel(rel @ Rel { ref name, ref attrs, .. }) => {
-                let ty = rel.foreign_key_type();
+            AttrOrRel::Rel(rel @ Rel { ref name, ref attrs, multiplicity: One | ZeroOrOne, .. }) if rel.is_stored(store) => {
+                let ty = rel.foreign_key_type(store)?;
                 attr_getters.push(quote!{
                     #(#attrs)*
                     #vis fn #name <DB: ?Sized + #db_name> (self, db: &DB) -> #ty {
-                        db.store().#ent[self].#name
+                        #get_store(db).#ent[self].#name
                     }
                 });
             }
+            AttrOrRel::Rel(_) => {}
         }
     }
 
     // Attribute setters
     let mut attr_setters = vec![];
-    for Attr {name, ty, ..} in entity.attrs() {
+    for attr @ Attr {name, ..} in entity.attrs().filter(|attr| !attr.readonly) {
+        let vis = attr.set_vis(store);
         let setter = format_ident!("set_{}", name);
+        let ty = attr.field_ty();
+        let mut unique_check = quote!();
+        let assign = if attr.unique {
+            let index = attr.index_field(entity);
+            let violation = attr.unique_violation(entity);
+            unique_check = quote! {
+                if store.#index.get(&value).map_or(false, |&id| id != self) {
+                    return Err(#violation);
+                }
+            };
+            quote! {
+                let prev = ::std::mem::replace(&mut store.#ent[self].#name, ::std::clone::Clone::clone(&value));
+                store.#index.remove(&prev);
+                store.#index.insert(value, self);
+            }
+        } else if attr.indexed {
+            let index = attr.index_field(entity);
+            quote! {
+                let prev = ::std::mem::replace(&mut store.#ent[self].#name, ::std::clone::Clone::clone(&value));
+                store.#index.remove(&(prev, self));
+                store.#index.insert((value, self), ());
+            }
+        } else {
+            quote!(store.#ent[self].#name = value;)
+        };
+        let recluster = entity.recluster(name, quote!(self));
+        let prev = quote!(&store.#ent[self].#name);
+        let log_removed = store.log_value(ent, name, attr.optional, "Removed", quote!(self), prev);
+        let log_inserted = store.log_value(ent, name, attr.optional, "Inserted", quote!(self), quote!(&value));
+        let checks = attr.generate_checks(entity, quote!(&value));
+        let row_checks = if entity.checks.is_empty() {
+            quote!()
+        } else {
+            let checks = entity.generate_row_checks(quote!(&row));
+            quote! {
+                let mut row = ::std::clone::Clone::clone(&store.#ent[self]);
+                row.#name = ::std::clone::Clone::clone(&value);
+                #checks
+            }
+        };
         attr_setters.push(quote! {
             #vis fn #setter <DB: ?Sized + #db_name> (self, db: &mut DB, value: #ty) -> Result<(),#err> {
-                db.store_mut().#ent[self].#name = value;
+                let store = #get_store_mut(db);
+                if !store.#ent.contains(self) {
+                    return Err(#err::EntityNotFound);
+                }
+                #checks
+                #row_checks
+                #unique_check
+                store.changes.begin_operation();
+                #log_removed
+                #log_inserted
+                #assign
+                #recluster
                 Ok(())
             }
         });
+        if attr.optional {
+            let clear = format_ident!("clear_{}", name);
+            attr_setters.push(quote! {
+                #vis fn #clear <DB: ?Sized + #db_name> (self, db: &mut DB) -> Result<(),#err> {
+                    self.#setter(db, None)
+                }
+            });
+        }
+    }
+
+    // Lookups by indexed attribute
+    let mut index_lookups = vec![];
+    for attr @ Attr {name, ty, ..} in entity.attrs().filter(|attr| attr.unique) {
+        let index = attr.index_field(entity);
+        let get_by = format_ident!("get_by_{}", name);
+        let doc = format!("Returns the entity whose `{}` is equal to the given value, if any.", name);
+        index_lookups.push(quote! {
+            #[doc = #doc]
+            #vis fn #get_by <DB: ?Sized + #db_name, Q: ?Sized + Ord> (db: &DB, value: &Q) -> Option<#key>
+            where
+                #ty: ::std::borrow::Borrow<Q>,
+            {
+                #get_store(db).#index.get(value).copied()
+            }
+        });
+    }
+    for attr @ Attr {name, ty, ..} in entity.attrs().filter(|attr| attr.indexed) {
+        let index = attr.index_field(entity);
+        let by = format_ident!("by_{}", name);
+        let range_by = format_ident!("range_by_{}", name);
+        let by_doc = format!("Returns the entities whose `{}` is equal to the given value.", name);
+        let range_by_doc = format!("Returns the entities whose `{}` is in the given range, in order.", name);
+        index_lookups.push(quote! {
+            #[doc = #by_doc]
+            #vis fn #by <'a, DB: ?Sized + #db_name, Q: ?Sized + ::std::borrow::ToOwned<Owned = #ty>> (db: &'a DB, value: &Q) -> impl Iterator<Item = #key> + 'a {
+                let value = value.to_owned();
+                #get_store(db).#index.range((::std::clone::Clone::clone(&value), <#key>::MIN)..=(value, <#key>::MAX)).map(|((_, id), _)| *id)
+            }
+
+            #[doc = #range_by_doc]
+            #vis fn #range_by <DB: ?Sized + #db_name> (db: &DB, range: impl ::std::ops::RangeBounds<#ty>) -> impl Iterator<Item = #key> + '_ {
+                let range = #CRATE::db::index_range(range, <#key>::MIN, <#key>::MAX);
+                #get_store(db).#index.range(range).map(|((_, id), _)| *id)
+            }
+        });
     }
 
 
     // Foreign-key setters
     let mut fk_setters = vec![];
-    for rel @ Rel { ref name, multiplicity, unique, .. } in entity.rels() {
+    for rel @ Rel { ref name, multiplicity, unique, .. } in entity.foreign_keys(store) {
+        if !store.is_local(&rel.destination) {
+            // the target can't be checked on insert, and deletions in the base store can't reach the row
+            return Err(Error::new(
+                name.span(),
+                format!("foreign keys to `{}` must be declared in an extension of `{}`", rel.destination, rel.destination),
+            ));
+        }
         let setter = format_ident!("set_{}", name);
-        let ty = rel.foreign_key_type();
+        let ty = rel.foreign_key_type(store)?;
         let index = rel.index_field(entity);
         let fk = &rel.name;
+        let dst_exists = store.contains_in(&rel.destination, quote!(store), quote!(fk));
+
+        // check that the target exists
+        let check_fk = if *multiplicity == ZeroOrOne {
+            quote! {
+                if let Some(fk) = fk {
+                    if !#dst_exists {
+                        return Err(#err::ForeignKeyViolation);
+                    }
+                }
+            }
+        } else {
+            quote! {
+                if !#dst_exists {
+                    return Err(#err::ForeignKeyViolation);
+                }
+            }
+        };
+
+        // an entity can't be its own ancestor
+        let check_cycle = if rel.is_hierarchy(entity) {
+            quote! {
+                if let Some(fk) = fk {
+                    if ::std::iter::successors(Some(fk), |&id| store.#ent[id].#fk).any(|id| id == self) {
+                        return Err(#err::CycleDetected);
+                    }
+                }
+            }
+        } else {
+            quote!()
+        };
 
         let body = match (multiplicity, unique) {
             (ZeroOrOne, true) => {
                 quote! {
                     if let Some(fk) = fk {
-                        match self.#index.contains(fk) {
+                        if store.#index.get(&fk).map_or(false, |&src| src != self) {
                             return Err(#err::RelationshipTooManyTargets);
                         }
                     }
@@ -428,12 +1827,12 @@ fn generate_entity(
             }
             (One, true) => {
                 quote! {
-                    match self.#index.contains(fk) {
+                    if store.#index.get(&fk).map_or(false, |&src| src != self) {
                         return Err(#err::RelationshipTooManyTargets);
                     }
                     let prev_fk = ::std::mem::replace(&mut store.#ent[self].#fk, fk);
-                    store.#index.remove(&(prev_fk, self));
-                    store.#index.insert((fk, self), ());
+                    store.#index.remove(&prev_fk);
+                    store.#index.insert(fk, self);
                 }
             }
             (One, false) => {
@@ -443,60 +1842,107 @@ fn generate_entity(
                     store.#index.insert((fk, self), ());
                 }
             }
-            _ => unimplemented!(),
+            (Many, _) => unreachable!(),
         };
 
+        let recluster = entity.recluster(name, quote!(self));
+        let optional = *multiplicity == ZeroOrOne;
+        let log_removed = store.log_value(ent, fk, optional, "Removed", quote!(self), quote!(&prev_fk));
+        let log_inserted = store.log_value(ent, fk, optional, "Inserted", quote!(self), quote!(&fk));
+        let row_checks = if entity.checks.is_empty() {
+            quote!()
+        } else {
+            let checks = entity.generate_row_checks(quote!(&row));
+            quote! {
+                let mut row = ::std::clone::Clone::clone(&store.#ent[self]);
+                row.#fk = fk;
+                #checks
+            }
+        };
         fk_setters.push(quote! {
             #vis fn #setter <DB: ?Sized + #db_name> (self, db: &mut DB, fk: #ty) -> Result<(),#err> {
-                let mut store = db.store_mut();
+                let store = #get_store_mut(db);
+                if !store.#ent.contains(self) {
+                    return Err(#err::EntityNotFound);
+                }
+                #check_fk
+                #check_cycle
+                #row_checks
                 #body
+                store.changes.begin_operation();
+                #log_removed
+                #log_inserted
+                #recluster
                 Ok(())
             }
         });
     }
 
+    // Inverse relationship getters, on the key type of the destination
+    let inverse_getters = generate_inverse_getters(store, entity)?;
+
+    // Hierarchy navigation
+    for rel in entity.foreign_keys(store).filter(|r| r.is_hierarchy(entity)) {
+        for method in generate_hierarchy_methods(store, entity, rel) {
+            fk_setters.push(method.inherent(vis));
+        }
+    }
+
+    // Many-to-many relationship getters and setters
+    for rel in entity.rels().filter(|r| r.is_junction(store)) {
+        for method in generate_junction_methods(store, entity, rel)? {
+            fk_setters.push(method.inherent(vis));
+        }
+    }
+
+    // Ordered relationships
+    for rel in entity.rels().filter(|r| r.ordered) {
+        for method in generate_list_methods(store, entity, rel)? {
+            fk_setters.push(method.inherent(vis));
+        }
+    }
+
     // Insert method
     let insert_method = {
-        // Integrity checks before inserting a new entity
-        let mut before_insert = TokenStream::new();
-        // Statements after inserting a new entity (update relation indices)
-        let mut update_indices = TokenStream::new();
+        let (before_insert, update_indices) = generate_insert_checks(store, entity);
 
-        for rel in entity.rels() {
-            let fk = &rel.name;
-            let index = rel.index_field(entity);
-            match rel.multiplicity {
-                ZeroOrOne => {
-                    // * to 0..1
-                    update_indices.append_all(
-                        quote! {
-                        if let Some(k) = data.#fk {
-                            self.#index.insert((k, next_id),());
-                        }
-                    }
-                    );
-                }
-                One => {
-                    // * to 1
-                    update_indices.append_all(
-                        quote! {
-                        self.#index.insert((data.#fk, next_id),());
-                    }
-                    );
-                }
-                _ => {
-                    todo!("unique constraints")
-                }
+        let insert = if entity.has_composite_key() {
+            quote!(Err(#err::KeyRequired))
+        } else {
+            quote! {
+                let id = self.#ent.next_id().ok_or(#err::IdsExhausted)?;
+                #CRATE::EntityStore::<#ent>::insert_with_id(self, id, f)
             }
-        }
+        };
+        let store_row = entity.store_row(quote!(row));
 
+        let log = entity.log_row(store, quote!(data), "Inserted");
         quote! {
             fn insert(&mut self, f: impl FnOnce(#key) -> #ent) -> Result<#key, #err> {
-                let next_id = self.#ent.next_id();
-                let data = f(next_id);
+                #insert
+            }
+
+            fn insert_with_id(&mut self, id: #key, f: impl FnOnce(#key) -> #ent) -> Result<#key, #err> {
+                if self.#ent.contains(id) {
+                    return Err(#err::DuplicateKey);
+                }
+                let data = f(id);
+                if data.id != id {
+                    return Err(#err::KeyMismatch);
+                }
                 #before_insert
+                // the indices and the change log are updated from `data`
+                let row = ::std::clone::Clone::clone(&data);
+                if !#store_row {
+                    return Err(#err::DuplicateKey);
+                }
+                self.changes.begin_operation();
+                {
+                    let store = &mut *self;
+                    #log
+                }
                 #update_indices
-                Ok(self.#ent.insert_at(data))
+                Ok(id)
             }
         }
     };
@@ -507,240 +1953,1913 @@ fn generate_entity(
         let mut update_foreign_keys = TokenStream::new();
 
         // index integrity
-        for rel in entity.rels() {
+        for attr in entity.attrs().filter(|attr| attr.indexed) {
+            let name = &attr.name;
+            let index = attr.index_field(entity);
+            update_indices.append_all(quote! {
+                store.#index.remove(&(::std::clone::Clone::clone(&data.#name), id));
+            });
+        }
+        for attr in entity.attrs().filter(|attr| attr.unique) {
+            let name = &attr.name;
+            let index = attr.index_field(entity);
+            update_indices.append_all(quote! {
+                store.#index.remove(&data.#name);
+            });
+        }
+        for rel in entity.foreign_keys(store) {
             let fk = &rel.name;
             let index = rel.index_field(entity);
-            match rel.multiplicity {
-                ZeroOrOne => {
+            match (rel.multiplicity, rel.unique) {
+                (ZeroOrOne, false) => {
                     // * to 0..1
                     update_indices.append_all(
                         quote! {
                             if let Some(k) = data.#fk {
-                                self.#index.remove(&(k, id));
+                                store.#index.remove(&(k, id));
                             }
                         }
                     );
                 }
-                One => {
+                (One, false) => {
                     // * to 1
                     update_indices.append_all(
                         quote! {
-                            self.#index.remove(&(data.#fk, id));
+                            store.#index.remove(&(data.#fk, id));
                         }
                     );
                 }
-                _ => {
-                    todo!("unique constraints")
+                (ZeroOrOne, true) => {
+                    // 0..1 to 0..1
+                    update_indices.append_all(quote! {
+                        if let Some(k) = data.#fk {
+                            store.#index.remove(&k);
+                        }
+                    });
                 }
+                (One, true) => {
+                    // 0..1 to 1
+                    update_indices.append_all(quote! {
+                        store.#index.remove(&data.#fk);
+                    });
+                }
+                (Many, _) => unreachable!(),
             }
         }
 
+        // remove the entity from both sides of many-to-many relationships
+        update_indices.append_all(generate_junction_cleanup(store, &entity.name)?);
+
         // removal process:
-        // - for each reference to the entity via foreign-keys:
-        //     - if delete mode is cascade: if there are any references in the index, recursively check that the entity can be deleted
-        //     - if delete mode is deny: return an error
+        // - check phase (`before_remove`): for each reference to the entity:
+        //     - if delete mode is cascade: recursively check that the referencing entities can be deleted
+        //     - if delete mode is deny: return an error if there are any references in the index
         //     - if delete mode is nullify: OK
-        // - remove the entity from the store
-        // - update indices and update foreign keys
-        //      - if delete mode is cascade: recursively remove all entities that reference the entity (using the index)
-        //      - if delete mode is nullify: set all foreign keys to null (using the index)
-
-        // Complications: entities can be removed via multiple cascade paths
-        // -> this suddenly increases the complexity by a lot
-
-
-        for (fk_ent, fk_rel) in store.foreign_key_refs(entity) {
-
-            let index = fk_rel.index_field(fk_ent);
-
-            for fk_rel in fk_ent.rels().filter(|r| &r.destination == ent) {
-                let index = fk_rel.index_field(fk_ent);
-                let fk_ent = &fk_ent.name;
-                let fk = &fk_rel.name;
-
-
-                // RelIndex::get_all: return all values matching the given key
-                // RelIndex::remove_all: remove all values matching the given key, returns an iterator over the removed values
-            }
+        //   All entities that will be removed are collected in the removal set, which also guards against
+        //   entities reachable via multiple cascade paths (or cycles).
+        // - apply phase (`remove_inner`, for each entity in the removal set):
+        //     - remove the entity from the store and update indices
+        //     - if delete mode is nullify: set the foreign keys of entities that are not removed to null (using the index)
+        //
+        // This applies to references to the entity, and to the entity as a subtype (e.g. `ShapeId::Circle(id)`).
+        let mut targets = vec![(&entity.name, None)];
+        if let Some(supertype) = &entity.supertype {
+            let supertype_key = store.entity_by_name(supertype)?.key_ty();
+            targets.push((supertype, Some(quote!(#supertype_key::#ent(id)))));
         }
+        for (target, target_id) in targets {
+            let mut check = TokenStream::new();
+            let mut nullify = TokenStream::new();
+            for (fk_ent, fk_rel) in store.foreign_key_refs(target) {
+                let src_ent = &fk_ent.name;
+                let fk = &fk_rel.name;
+                let sources = fk_rel.sources_of(fk_ent, store, quote!(store), quote!(id));
 
-        quote! {
-            fn remove(&mut self, id: #key) -> Result<#ent, #err> {
-
-                let data = self.#ent.remove(id).ok_or(#err::EntityNotFound)?;
-                #before_remove
-                #update_indices
+                match (fk_rel.delete_rule(), fk_rel.multiplicity) {
+                    (DeleteRule::Deny, _) => {
+                        check.append_all(quote! {
+                            if #sources.next().is_some() {
+                                return Err(#err::RelationshipDeniedDelete);
+                            }
+                        });
+                    }
+                    (DeleteRule::Cascade, _) => {
+                        check.append_all(quote! {
+                            for src in #sources {
+                                #src_ent::before_remove(store, src, removing)?;
+                            }
+                        });
+                    }
+                    (DeleteRule::Nullify, One) => {
+                        return Err(Error::new(
+                            fk.span(),
+                            "a mandatory relationship cannot be nullified on delete",
+                        ));
+                    }
+                    (DeleteRule::Nullify, ZeroOrOne) => {
+                        let src = fk_ent.key_ty();
+                        let index = fk_rel.index_field(fk_ent);
+                        let remove_entry = if fk_rel.unique {
+                            quote!(store.#index.remove(&id);)
+                        } else {
+                            quote!(store.#index.remove(&(id, src));)
+                        };
+                        let log = store.log_change(src_ent, Some(fk), "Removed", quote!(src, id));
+                        if fk_ent.extends.is_some() {
+                            // the sources are entities of the base store, they are never removed along with the entity
+                            let field = fk_ent.extension_field(fk);
+                            nullify.append_all(quote! {
+                                let sources: Vec<#src> = #sources.collect();
+                                for src in sources {
+                                    store.#field.remove(&src);
+                                    #remove_entry
+                                    #log
+                                }
+                            });
+                        } else {
+                            let recluster = fk_ent.recluster(fk, quote!(src));
+                            nullify.append_all(quote! {
+                                let sources: Vec<#src> = #sources.filter(|src| !removing.#src_ent.contains(src)).collect();
+                                for src in sources {
+                                    store.#src_ent[src].#fk = None;
+                                    #remove_entry
+                                    #recluster
+                                    #log
+                                }
+                            });
+                        }
+                    }
+                    (DeleteRule::Nullify, Many) => {
+                        // junction table entries are removed along with the entity
+                    }
+                }
+            }
+
+            match target_id {
+                None => {
+                    before_remove.append_all(check);
+                    update_foreign_keys.append_all(nullify);
+                }
+                Some(target_id) => {
+                    nullify.append_all(generate_junction_cleanup(store, target)?);
+                    if !check.is_empty() {
+                        before_remove.append_all(quote! {
+                            {
+                                let id = #target_id;
+                                #check
+                            }
+                        });
+                    }
+                    if !nullify.is_empty() {
+                        update_foreign_keys.append_all(quote! {
+                            {
+                                let id = #target_id;
+                                #nullify
+                            }
+                        });
+                    }
+                }
+            }
+        }
+
+        // entities whose composite key contains the key of the entity are removed along with it
+        for src in store.tables() {
+            for (i, _) in store.key_refs(src).filter(|(_, dst)| dst.name == entity.name) {
+                let src_ent = &src.name;
+                let src_key = src.key_ty();
+                let sources = if i == 0 {
+                    quote!(store.#src_ent.range(#src_key::prefix_1(id)).map(|row| row.id))
+                } else {
+                    let i = syn::Index::from(i);
+                    quote!(store.#src_ent.keys().filter(|key| key.#i == id))
+                };
+                before_remove.append_all(quote! {
+                    for src in #sources {
+                        #src_ent::before_remove(store, src, removing)?;
+                    }
+                });
+            }
+        }
+
+        let log = entity.log_row(store, quote!(data), "Removed");
+        quote! {
+            /// Checks that the entity can be removed, and collects all entities that would be removed along with it.
+            fn before_remove(store: &#store_ty, id: #key, removing: &mut #removal_ty) -> Result<(), #err> {
+                if !removing.#ent.insert(id) {
+                    // already visited
+                    return Ok(());
+                }
+                #before_remove
+                Ok(())
+            }
+
+            /// Removes the entity from the store, updates indices and nullifies references to it.
+            ///
+            /// `removing` is the removal set computed by `before_remove`.
+            fn remove_inner(store: &mut #store_ty, id: #key, removing: &#removal_ty) -> Option<#ent> {
+                let data = store.#ent.remove(id)?;
+                #log
+                #update_indices
+                #update_foreign_keys
+                Some(data)
+            }
+        }
+    };
+
+    // Constraints of the entity and its attributes
+    let mut constraint_checks = TokenStream::new();
+    for attr in entity.attrs() {
+        let name = &attr.name;
+        constraint_checks.append_all(attr.generate_checks(entity, quote!(&self.#name)));
+    }
+    constraint_checks.append_all(entity.generate_row_checks(quote!(self)));
+
+    // Initializer
+    let initializer = {
+        let new_ent = format_ident!("New{}", ent);
+        let doc = format!("Initializer of a new `{}`, inserted with [`{}::insert`].", ent, new_ent);
+        let names: Vec<_> = row_fields.iter().map(|(name, _, _)| name).collect();
+        let tys = row_fields.iter().map(|(_, ty, _)| ty);
+        let values = row_fields.iter().map(|(name, _, default)| match default {
+            Some(default) => default.clone(),
+            None => quote!(#name),
+        });
+        let mut params: Vec<_> = row_fields
+            .iter()
+            .filter(|(_, _, default)| default.is_none())
+            .map(|(name, ty, _)| quote!(#name: #ty))
+            .collect();
+        let (id_field, id_value, insert) = if entity.has_composite_key() {
+            params.insert(0, quote!(id: #key));
+            (
+                quote!(#vis id: #key,),
+                quote!(id,),
+                quote!(<DB as #db_name>::insert_with_id::<#ent>(db, self.id, |id| #ent { id, #(#names,)* })),
+            )
+        } else {
+            (quote!(), quote!(), quote!(<DB as #db_name>::insert::<#ent>(db, |id| #ent { id, #(#names,)* })))
+        };
+        let default_impl = if params.is_empty() {
+            quote! {
+                impl ::std::default::Default for #new_ent {
+                    fn default() -> Self {
+                        Self::new()
+                    }
+                }
+            }
+        } else {
+            quote!()
+        };
+        quote! {
+            #[doc = #doc]
+            #[derive(Clone)]
+            #vis struct #new_ent {
+                #id_field
+                #(#vis #names: #tys,)*
+            }
+
+            impl #new_ent {
+                /// Creates an initializer with the given values, and default values for the other fields.
+                #[allow(clippy::too_many_arguments)]
+                #vis fn new(#(#params),*) -> Self {
+                    Self {
+                        #id_value
+                        #(#names: #values,)*
+                    }
+                }
+
+                /// Inserts the entity, checking all constraints.
+                #vis fn insert <DB: ?Sized + #db_name> (self, db: &mut DB) -> Result<#key, #err> {
+                    let Self { #(#names,)* .. } = self;
+                    #insert
+                }
+            }
+
+            #default_impl
+        }
+    };
+
+    // Key type
+    let key_attrs = forward_attrs(&entity.id_attrs, KEY_DERIVES)?;
+    let (key_def, key_consts, key_impls, range_method);
+    if entity.has_composite_key() {
+        let components: Vec<_> = entity.keys.iter().collect();
+        key_def = quote! {
+            #(#key_attrs)*
+            #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+            #vis struct #key(#(#vis #components),*);
+        };
+
+        // keys that start with the first `n` components of the key, for each proper prefix
+        let mut prefixes = TokenStream::new();
+        for n in 1..components.len() {
+            let prefix = format_ident!("prefix_{}", n);
+            let args: Vec<_> = (0..n).map(|i| format_ident!("k{}", i)).collect();
+            let arg_tys = &components[..n];
+            let rest = &components[n..];
+            prefixes.append_all(quote! {
+                /// Returns the range of keys that start with the given components.
+                #vis fn #prefix(#(#args: #arg_tys),*) -> ::std::ops::RangeInclusive<Self> {
+                    Self(#(#args,)* #(<#rest>::MIN),*)..=Self(#(#args,)* #(<#rest>::MAX),*)
+                }
+            });
+        }
+        key_consts = quote! {
+            #vis const MIN: Self = Self(#(<#components>::MIN),*);
+            #vis const MAX: Self = Self(#(<#components>::MAX),*);
+
+            #prefixes
+        };
+        key_impls = quote!();
+        range_method = quote! {
+            /// Returns the entities whose keys are in the given range, in key order.
+            ///
+            /// Use the `prefix_*` methods of the key type to look up entities by key prefix.
+            #vis fn range <DB: ?Sized + #db_name> (db: &DB, range: impl ::std::ops::RangeBounds<#key>) -> impl Iterator<Item = &#ent> + '_ {
+                // keys are `Copy`: the returned iterator doesn't need to borrow the range
+                let bounds = (range.start_bound().cloned(), range.end_bound().cloned());
+                #get_store(db).#ent.range(bounds)
+            }
+        };
+    } else {
+        key_def = quote! {
+            #(#key_attrs)*
+            #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+            #[repr(transparent)]
+            #vis struct #key(::std::num::NonZeroU32);
+        };
+        key_consts = quote! {
+            #vis const MIN: Self = Self(::std::num::NonZeroU32::MIN);
+            #vis const MAX: Self = Self(::std::num::NonZeroU32::MAX);
+        };
+        key_impls = quote! {
+            impl #CRATE::EntityId for #key {
+                fn to_u32(self) -> u32 {
+                    self.0.get() - 1
+                }
+
+                fn from_u32(i: u32) -> Self {
+                    Self(unsafe { ::std::num::NonZeroU32::new_unchecked(i + 1) })
+                }
+            }
+        };
+        range_method = quote!();
+    }
+
+    let update_method = generate_update_method(store, entity)?;
+    let row_attrs = forward_attrs(&entity.attrs, &["Clone"])?;
+    let res = quote! {
+        #(#row_attrs)*
+        #[derive(Clone)]
+        #vis struct #ent {
+            id: #key,
+            #(#fields,)*
+        }
+
+        #initializer
+
+        impl ::std::ops::Index<#key> for #store_ty {
+            type Output = #ent;
+            fn index(&self, key: #key) -> &Self::Output {
+                &self.#ent[key]
+            }
+        }
+
+        impl ::std::ops::IndexMut<#key> for #store_ty {
+            fn index_mut(&mut self, key: #key) -> &mut Self::Output {
+                &mut self.#ent[key]
+            }
+        }
+
+        impl #CRATE::EntityStore<#ent> for #store_ty {
+            #insert_method
+
+            fn get(&self, id: #key) -> Option<&#ent> {
+                self.#ent.get(id)
+            }
+
+            fn contains(&self, id: #key) -> bool {
+                self.#ent.contains(id)
+            }
+
+            fn len(&self) -> usize {
+                self.#ent.len()
+            }
+
+            fn removal(&self, id: #key) -> Result<#removal_ty, #err> {
+                if !self.#ent.contains(id) {
+                    return Err(#err::EntityNotFound);
+                }
+                let mut removing = #removal_ty::default();
+                #ent::before_remove(self, id, &mut removing)?;
+                Ok(removing)
+            }
+
+            fn remove(&mut self, id: #key) -> Result<#ent, #err> {
+                let removing = #CRATE::EntityStore::<#ent>::removal(self, id)?;
+                self.changes.begin_operation();
+                let data = #ent::remove_inner(self, id, &removing).unwrap();
+                self.remove_all(&removing);
                 Ok(data)
             }
+
+            fn delta<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = #CRATE::Delta<&'a #ent>> + 'a {
+                self.#ent.delta(&other.#ent)
+            }
+
+            fn iter<'a>(&'a self) -> impl Iterator<Item = &'a #ent> + 'a {
+                self.#ent.iter()
+            }
+        }
+
+        impl #ent {
+            #vis fn all <DB: ?Sized + #db_name> (db: &DB) -> impl Iterator<Item = &#ent> + '_ {
+                #get_store(db).#ent.values()
+            }
+
+            #range_method
+
+            #(#index_lookups)*
+
+            #update_method
+
+            /// Checks the `#[check]` constraints of the entity and its attributes.
+            fn check_constraints(&self) -> Result<(), #err> {
+                #constraint_checks
+                Ok(())
+            }
+
+            #remove_method
+        }
+
+        #key_def
+
+        impl #key {
+            #key_consts
+
+            /// Removes the entity, applying the delete rules of the relationships that point to it.
+            #vis fn remove <DB: ?Sized + #db_name> (self, db: &mut DB) -> Result<#ent, #err> {
+                <DB as #db_name>::remove::<#ent>(db, self)
+            }
+
+            #(#attr_getters)*
+            #(#attr_setters)*
+            #(#fk_setters)*
+        }
+
+        #inverse_getters
+
+        impl #CRATE::EntityKey for #key {
+            type Entity = #ent;
+        }
+
+        #key_impls
+
+        impl #CRATE::Entity for #ent {
+            type Id = #key;
+            type Store = #store_ty;
+            fn id(&self) -> Self::Id {
+                self.id
+            }
+        }
+    };
+
+    Ok(res)
+}
+
+/// Checks the declarations of abstract entities and their subtypes, and copies the attributes of abstract entities
+/// into their subtypes.
+fn resolve_inheritance(store: &mut Store) -> Result<(), Error> {
+    for i in 0..store.entities.len() {
+        let entity = &store.entities[i];
+        if entity.is_abstract {
+            let unsupported = if entity.extends.is_some() || entity.supertype.is_some() || entity.has_composite_key() {
+                Some("abstract entities must have a single key, and cannot be extensions or subtypes")
+            } else if entity.rels().next().is_some() {
+                Some("relationships must be declared in the subtypes of abstract entities")
+            } else if entity.cluster.is_some() || !entity.checks.is_empty() {
+                Some("abstract entities cannot be clustered or have row constraints")
+            } else if entity.attrs().any(|attr| attr.unique) {
+                Some("attributes of abstract entities cannot be unique")
+            } else if store.subtypes(&entity.name).next().is_none() {
+                Some("abstract entity without subtypes")
+            } else {
+                None
+            };
+            if let Some(msg) = unsupported {
+                return Err(Error::new(entity.name.span(), msg));
+            }
+        }
+
+        let Some(supertype) = &entity.supertype else {
+            continue;
+        };
+        let Some(parent) = store.abstracts().find(|e| &e.name == supertype) else {
+            return Err(Error::new(
+                supertype.span(),
+                format!("`{}` is not an abstract entity of this store", supertype),
+            ));
+        };
+        if let Some(attr) = parent.attrs().find(|attr| entity.attrs().any(|a| a.name == attr.name)) {
+            return Err(Error::new(
+                attr.name.span(),
+                format!("`{}` is already declared in `{}`", attr.name, entity.name),
+            ));
+        }
+        let inherited: Vec<_> = parent.attrs().cloned().map(|attr| AttrOrRel::Attr(Box::new(attr))).collect();
+        let entity = &mut store.entities[i];
+        let items = std::mem::take(&mut entity.items);
+        entity.items = inherited.into_iter().chain(items).collect();
+    }
+    Ok(())
+}
+
+/// Returns the statements that check the integrity of a new row `data` before it is inserted, and those that add it to
+/// the indices of the store, as a pair `(checks, update_indices)`.
+///
+/// The statements refer to the store as `self`.
+fn generate_insert_checks(store: &Store, entity: &Entity) -> (TokenStream, TokenStream) {
+    let err = quote!(#CRATE::Error);
+    // Integrity checks before inserting a new entity
+    let mut before_insert = quote!(data.check_constraints()?;);
+    // Statements after inserting a new entity (update relation indices)
+    let mut update_indices = TokenStream::new();
+
+    for (i, dst) in store.key_refs(entity) {
+        let i = syn::Index::from(i);
+        let dst_exists = store.contains_in(&dst.name, quote!(self), quote!(data.id.#i));
+        before_insert.append_all(quote! {
+            if !#dst_exists {
+                return Err(#err::ForeignKeyViolation);
+            }
+        });
+    }
+    for attr in entity.attrs().filter(|attr| attr.indexed) {
+        let name = &attr.name;
+        let index = attr.index_field(entity);
+        update_indices.append_all(quote! {
+            self.#index.insert((::std::clone::Clone::clone(&data.#name), id), ());
+        });
+    }
+    for attr in entity.attrs().filter(|attr| attr.unique) {
+        let name = &attr.name;
+        let index = attr.index_field(entity);
+        let violation = attr.unique_violation(entity);
+        before_insert.append_all(quote! {
+            if self.#index.contains_key(&data.#name) {
+                return Err(#violation);
+            }
+        });
+        update_indices.append_all(quote! {
+            self.#index.insert(::std::clone::Clone::clone(&data.#name), id);
+        });
+    }
+
+    for rel in entity.foreign_keys(store) {
+        let fk = &rel.name;
+        let index = rel.index_field(entity);
+        let dst_ent = &rel.destination;
+
+        // check that the target exists
+        if rel.multiplicity == ZeroOrOne {
+            let dst_exists = store.contains_in(dst_ent, quote!(self), quote!(k));
+            before_insert.append_all(quote! {
+                if let Some(k) = data.#fk {
+                    if !#dst_exists {
+                        return Err(#err::ForeignKeyViolation);
+                    }
+                }
+            });
+        } else {
+            let dst_exists = store.contains_in(dst_ent, quote!(self), quote!(data.#fk));
+            before_insert.append_all(quote! {
+                if !#dst_exists {
+                    return Err(#err::ForeignKeyViolation);
+                }
+            });
+        }
+
+        match (rel.multiplicity, rel.unique) {
+            (ZeroOrOne, false) => {
+                // * to 0..1
+                update_indices.append_all(
+                    quote! {
+                    if let Some(k) = data.#fk {
+                        self.#index.insert((k, id),());
+                    }
+                }
+                );
+            }
+            (One, false) => {
+                // * to 1
+                update_indices.append_all(
+                    quote! {
+                    self.#index.insert((data.#fk, id),());
+                }
+                );
+            }
+            (ZeroOrOne, true) => {
+                // 0..1 to 0..1
+                before_insert.append_all(quote! {
+                    if let Some(k) = data.#fk {
+                        if self.#index.contains_key(&k) {
+                            return Err(#err::RelationshipTooManyTargets);
+                        }
+                    }
+                });
+                update_indices.append_all(quote! {
+                    if let Some(k) = data.#fk {
+                        self.#index.insert(k, id);
+                    }
+                });
+            }
+            (One, true) => {
+                // 0..1 to 1
+                before_insert.append_all(quote! {
+                    if self.#index.contains_key(&data.#fk) {
+                        return Err(#err::RelationshipTooManyTargets);
+                    }
+                });
+                update_indices.append_all(quote! {
+                    self.#index.insert(data.#fk, id);
+                });
+            }
+            (Many, _) => unreachable!(),
+        }
+    }
+
+    (before_insert, update_indices)
+}
+
+/// Generates the `update` method of an entity, which modifies several attributes and foreign keys of a row at once.
+///
+/// The modified row is compared to the previous one: all checks are made on the modified values before anything is
+/// changed, then the indices of the modified values are updated and their changes are recorded under a single
+/// timestamp. Attributes without an index are not compared.
+fn generate_update_method(store: &Store, entity: &Entity) -> Result<TokenStream, Error> {
+    let ent = &entity.name;
+    let key = entity.key_ty();
+    let store_ty = store.store_type();
+    let err = quote!(#CRATE::Error);
+    let vis = &store.vis;
+    let db_name = &store.name;
+
+    let mut keep = TokenStream::new();
+    let mut checks = TokenStream::new();
+    let mut apply = TokenStream::new();
+
+    for attr in entity.attrs() {
+        let name = &attr.name;
+        if attr.readonly {
+            // read-only attributes need not be comparable: their modifications are discarded
+            keep.append_all(quote! {
+                row.#name = ::std::clone::Clone::clone(&prev.#name);
+            });
+            continue;
+        }
+
+        let mut update_index = quote!();
+        if attr.unique {
+            let index = attr.index_field(entity);
+            let violation = attr.unique_violation(entity);
+            checks.append_all(quote! {
+                if store.#index.get(&row.#name).map_or(false, |&other| other != id) {
+                    return Err(#violation);
+                }
+            });
+            update_index = quote! {
+                store.#index.remove(&prev.#name);
+                store.#index.insert(::std::clone::Clone::clone(&row.#name), id);
+            };
+        } else if attr.indexed {
+            let index = attr.index_field(entity);
+            update_index = quote! {
+                store.#index.remove(&(::std::clone::Clone::clone(&prev.#name), id));
+                store.#index.insert((::std::clone::Clone::clone(&row.#name), id), ());
+            };
+        }
+        // attributes without an index need not be comparable: their modifications are recorded as a single change
+        // of the row
+        if attr.unique || attr.indexed {
+            let log_removed = store.log_value(ent, name, attr.optional, "Removed", quote!(id), quote!(&prev.#name));
+            let log_inserted = store.log_value(ent, name, attr.optional, "Inserted", quote!(id), quote!(&row.#name));
+            apply.append_all(quote! {
+                if row.#name != prev.#name {
+                    #log_removed
+                    #log_inserted
+                    #update_index
+                }
+            });
+        }
+    }
+    if entity.has_unindexed_attrs() {
+        apply.append_all(store.log_change(ent, None, "Updated", quote!(id)));
+    }
+
+    for rel in entity.foreign_keys(store) {
+        let fk = &rel.name;
+        let index = rel.index_field(entity);
+        let optional = rel.is_optional_one();
+        let dst_exists = store.contains_in(&rel.destination, quote!(store), quote!(fk));
+
+        let mut check = quote! {
+            if !#dst_exists {
+                return Err(#err::ForeignKeyViolation);
+            }
+        };
+        if rel.unique {
+            check.append_all(quote! {
+                if store.#index.get(&fk).map_or(false, |&src| src != id) {
+                    return Err(#err::RelationshipTooManyTargets);
+                }
+            });
+        }
+        if rel.is_hierarchy(entity) {
+            check.append_all(quote! {
+                if ::std::iter::successors(Some(fk), |&other| store.#ent[other].#fk).any(|other| other == id) {
+                    return Err(#err::CycleDetected);
+                }
+            });
+        }
+        let (remove_entry, insert_entry) = if rel.unique {
+            (quote!(store.#index.remove(&fk);), quote!(store.#index.insert(fk, id);))
+        } else {
+            (quote!(store.#index.remove(&(fk, id));), quote!(store.#index.insert((fk, id), ());))
+        };
+        let log_removed = store.log_value(ent, fk, optional, "Removed", quote!(id), quote!(&prev.#fk));
+        let log_inserted = store.log_value(ent, fk, optional, "Inserted", quote!(id), quote!(&row.#fk));
+        if optional {
+            checks.append_all(quote! {
+                if row.#fk != prev.#fk {
+                    if let Some(fk) = row.#fk {
+                        #check
+                    }
+                }
+            });
+            apply.append_all(quote! {
+                if row.#fk != prev.#fk {
+                    #log_removed
+                    #log_inserted
+                    if let Some(fk) = prev.#fk {
+                        #remove_entry
+                    }
+                    if let Some(fk) = row.#fk {
+                        #insert_entry
+                    }
+                }
+            });
+        } else {
+            checks.append_all(quote! {
+                if row.#fk != prev.#fk {
+                    let fk = row.#fk;
+                    #check
+                }
+            });
+            apply.append_all(quote! {
+                if row.#fk != prev.#fk {
+                    #log_removed
+                    #log_inserted
+                    {
+                        let fk = prev.#fk;
+                        #remove_entry
+                    }
+                    {
+                        let fk = row.#fk;
+                        #insert_entry
+                    }
+                }
+            });
+        }
+    }
+
+    let recluster = match entity.cluster_key(quote!(store.#ent[id])) {
+        Some(cluster_key) => quote! {
+            let key = #cluster_key;
+            store.#ent.recluster(id, key);
+        },
+        None => quote!(),
+    };
+
+    Ok(quote! {
+        /// Modifies several attributes and relationships of the entity at once.
+        ///
+        /// All constraints are checked on the modified row before it replaces the previous one: if one of them
+        /// fails, the entity is left unchanged. The key and the read-only attributes of the entity cannot be
+        /// modified: their previous values are kept. The changes to each attribute and relationship are recorded in
+        /// the change log, as a single operation, except for attributes without an index: they are not compared, and
+        /// their modifications are recorded as a single change of the entity (e.g. `Track_Updated`).
+        #vis fn update <DB: ?Sized + #db_name> (db: &mut DB, id: #key, f: impl FnOnce(&mut #ent)) -> Result<(), #err> {
+            let store = #CRATE::HasStore::<#store_ty>::store_mut(db);
+            let prev = ::std::clone::Clone::clone(store.#ent.get(id).ok_or(#err::EntityNotFound)?);
+            let mut row = ::std::clone::Clone::clone(&prev);
+            f(&mut row);
+            row.id = id;
+            #keep
+
+            row.check_constraints()?;
+            #checks
+
+            store.changes.begin_operation();
+            #apply
+            store.#ent[id] = row;
+            #recluster
+            Ok(())
+        }
+    })
+}
+
+/// Generates the key type of an abstract entity, which is an enum of the keys of its subtypes, and the type of
+/// references to its rows (e.g. `ShapeId` and `Shape<'a>` for `abstract Shape(ShapeId)`).
+///
+/// Attribute getters and setters dispatch to the subtype.
+fn generate_abstract(store: &Store, entity: &Entity) -> Result<TokenStream, Error> {
+    let ent = &entity.name;
+    let key = entity.key_ty();
+    let store_ty = store.store_type();
+    let err = quote!(#CRATE::Error);
+    let vis = &store.vis;
+    let db_name = &store.name;
+    let subtypes: Vec<_> = store.subtypes(ent).map(|e| &e.name).collect();
+    let subtype_keys: Vec<_> = store.subtypes(ent).map(|e| e.key_ty()).collect();
+    let first = (&subtypes[0], &subtype_keys[0]);
+    let last = (subtypes.last().unwrap(), subtype_keys.last().unwrap());
+    let (first, first_key, last, last_key) = (first.0, first.1, last.0, last.1);
+
+    let mut methods = vec![];
+    for attr in entity.attrs() {
+        let name = &attr.name;
+        let setter = format_ident!("set_{}", name);
+        let ty = &attr.ty;
+        let field_ty = attr.field_ty();
+        let attrs = &attr.attrs;
+        let getter_ty = if attr.optional { quote!(Option<&#ty>) } else { quote!(&#ty) };
+        let getter_vis = attr.vis(store);
+        methods.push(quote! {
+            #(#attrs)*
+            #getter_vis fn #name <DB: ?Sized + #db_name> (self, db: &DB) -> #getter_ty {
+                match self {
+                    #(Self::#subtypes(id) => id.#name(db),)*
+                }
+            }
+        });
+        if attr.readonly {
+            continue;
+        }
+        let vis = attr.set_vis(store);
+        methods.push(quote! {
+            #vis fn #setter <DB: ?Sized + #db_name> (self, db: &mut DB, value: #field_ty) -> Result<(),#err> {
+                match self {
+                    #(Self::#subtypes(id) => id.#setter(db, value),)*
+                }
+            }
+        });
+        if attr.optional {
+            let clear = format_ident!("clear_{}", name);
+            methods.push(quote! {
+                #vis fn #clear <DB: ?Sized + #db_name> (self, db: &mut DB) -> Result<(),#err> {
+                    self.#setter(db, None)
+                }
+            });
+        }
+    }
+
+    let key_attrs = forward_attrs(&entity.id_attrs, KEY_DERIVES)?;
+    let row_attrs = forward_attrs(&entity.attrs, &["Copy", "Clone"])?;
+    let row_doc = format!("A reference to a row of a subtype of `{}`.", ent);
+    Ok(quote! {
+        #(#key_attrs)*
+        #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
+        #vis enum #key {
+            #(#subtypes(#subtype_keys),)*
+        }
+
+        #(
+            impl From<#subtype_keys> for #key {
+                fn from(id: #subtype_keys) -> Self {
+                    Self::#subtypes(id)
+                }
+            }
+        )*
+
+        impl #key {
+            #vis const MIN: Self = Self::#first(<#first_key>::MIN);
+            #vis const MAX: Self = Self::#last(<#last_key>::MAX);
+
+            fn exists_in(self, store: &#store_ty) -> bool {
+                match self {
+                    #(Self::#subtypes(id) => store.#subtypes.contains(id),)*
+                }
+            }
+
+            /// Returns the row of the entity.
+            #vis fn get <DB: ?Sized + #db_name> (self, db: &DB) -> #ent<'_> {
+                let store = #CRATE::HasStore::<#store_ty>::store(db);
+                match self {
+                    #(Self::#subtypes(id) => #ent::#subtypes(&store.#subtypes[id]),)*
+                }
+            }
+
+            /// Removes the entity, applying the delete rules of the relationships that point to it.
+            #vis fn remove <DB: ?Sized + #db_name> (self, db: &mut DB) -> Result<(), #err> {
+                match self {
+                    #(Self::#subtypes(id) => id.remove(db).map(|_| ()),)*
+                }
+            }
+
+            #(#methods)*
+        }
+
+        #[doc = #row_doc]
+        #(#row_attrs)*
+        #[derive(Copy, Clone)]
+        #vis enum #ent<'a> {
+            #(#subtypes(&'a #subtypes),)*
+        }
+
+        impl<'a> #ent<'a> {
+            /// Iterates over the entities of all subtypes.
+            #vis fn all <DB: ?Sized + #db_name> (db: &'a DB) -> impl Iterator<Item = #ent<'a>> + 'a {
+                let iter = ::std::iter::empty();
+                #(let iter = iter.chain(#subtypes::all(db).map(#ent::#subtypes));)*
+                iter
+            }
+
+            #vis fn id(&self) -> #key {
+                match self {
+                    #(#ent::#subtypes(row) => #key::#subtypes(row.id),)*
+                }
+            }
+        }
+    })
+}
+
+/// Checks the names of entities and their members, and the declarations of relationships: destinations, inverses,
+/// and the pairing of multiplicities.
+///
+/// All errors are reported at once.
+fn validate_schema(store: &Store) -> Result<(), Error> {
+    let mut errors = vec![];
+    for (i, entity) in store.entities.iter().enumerate() {
+        if store.entities[..i].iter().any(|e| e.name == entity.name) {
+            errors.push(Error::new(
+                entity.name.span(),
+                format!("`{}` is already declared in this store", entity.name),
+            ));
+        }
+
+        let mut names = vec![];
+        for item in entity.items.iter() {
+            let name = match item {
+                AttrOrRel::Attr(attr) => &attr.name,
+                AttrOrRel::Rel(rel) => &rel.name,
+            };
+            if names.contains(&name) {
+                errors.push(Error::new(
+                    name.span(),
+                    format!("`{}` is already declared in `{}`", name, entity.name),
+                ));
+            } else if name == "id" && entity.extends.is_none() {
+                errors.push(Error::new(name.span(), "`id` is reserved for the key of the entity"));
+            }
+            names.push(name);
+        }
+
+        for rel in entity.rels() {
+            validate_rel(store, entity, rel, &mut errors);
+        }
+
+        // components of composite keys that are keys of other entities are checked like foreign keys, which is only
+        // supported for entities stored in a table with keys of their own
+        for key in entity.keys.iter().filter(|_| entity.has_composite_key()) {
+            let unsupported = store.entities.iter().filter(|e| e.extends.is_none()).any(|e| {
+                (e.is_abstract && e.keys.first() == Some(key)) || (e.has_composite_key() && *key == format!("{}Id", e.name))
+            });
+            if unsupported {
+                errors.push(Error::new(
+                    key.span(),
+                    "the keys of abstract entities and of entities with composite keys cannot be components of keys",
+                ));
+            }
+        }
+    }
+
+    // an inverse that the destination doesn't declare is navigated with a method of the destination key, which can
+    // only be generated for one relationship
+    let mut inverses: Vec<(&Ident, &Ident, &Entity, &Rel)> = vec![];
+    for entity in store.entities.iter() {
+        for rel in entity.rels() {
+            let Some(inv_name) = &rel.inverse else {
+                continue;
+            };
+            let declared = store.entity_by_name(&rel.destination).is_ok_and(|dst| {
+                dst.items.iter().any(|item| match item {
+                    AttrOrRel::Attr(attr) => &attr.name == inv_name,
+                    AttrOrRel::Rel(rel) => &rel.name == inv_name,
+                })
+            });
+            if declared {
+                continue;
+            }
+            let other = inverses.iter().find(|(dst, name, ..)| *dst == &rel.destination && *name == inv_name);
+            if let Some((_, _, other_entity, other_rel)) = other {
+                errors.push(Error::new(
+                    inv_name.span(),
+                    format!(
+                        "`{}.{}` is already the inverse of `{}.{}`",
+                        rel.destination, inv_name, other_entity.name, other_rel.name
+                    ),
+                ));
+            } else {
+                inverses.push((&rel.destination, inv_name, entity, rel));
+            }
+        }
+    }
+
+    let mut errors = errors.into_iter();
+    match errors.next() {
+        Some(mut error) => {
+            error.extend(errors);
+            Err(error)
+        }
+        None => Ok(()),
+    }
+}
+
+/// Checks the declaration of a relationship, and its pairing with its inverse.
+fn validate_rel(store: &Store, entity: &Entity, rel: &Rel, errors: &mut Vec<Error>) {
+    let name = &rel.name;
+    if rel.unique && rel.multiplicity == Many {
+        errors.push(Error::new(name.span(), "to-many relationships cannot be unique"));
+    }
+    if (rel.multiplicity, rel.delete_rule) == (One, Some(DeleteRule::Nullify)) {
+        errors.push(Error::new(name.span(), "a mandatory relationship cannot be nullified on delete"));
+    }
+
+    let dst_name = &rel.destination;
+    if store.extensions().any(|e| &e.name == dst_name) {
+        errors.push(Error::new(
+            dst_name.span(),
+            format!("`{}` is an extension: relationships must point to the extended entity", dst_name),
+        ));
+        return;
+    }
+    let dst = match store.entity_by_name(dst_name) {
+        Ok(dst) => dst,
+        // an entity of the base store
+        Err(_) if store.base.is_some() => return,
+        Err(_) => {
+            errors.push(Error::new(dst_name.span(), format!("unknown entity `{}`", dst_name)));
+            return;
+        }
+    };
+
+    let Some(inv_name) = &rel.inverse else {
+        return;
+    };
+    let inv = match dst.items.iter().find(|item| match item {
+        AttrOrRel::Attr(attr) => &attr.name == inv_name,
+        AttrOrRel::Rel(rel) => &rel.name == inv_name,
+    }) {
+        // only navigated from the destination
+        None => return,
+        Some(AttrOrRel::Attr(_)) => {
+            errors.push(Error::new(
+                inv_name.span(),
+                format!("`{}.{}` is an attribute, not a relationship", dst.name, inv_name),
+            ));
+            return;
+        }
+        Some(AttrOrRel::Rel(inv)) => inv,
+    };
+    if ::std::ptr::eq(inv, rel) {
+        errors.push(Error::new(inv_name.span(), "a relationship cannot be its own inverse"));
+        return;
+    }
+    if &inv.destination != entity.target_name() {
+        errors.push(Error::new(
+            inv_name.span(),
+            format!("`{}.{}` doesn't point back to `{}`", dst.name, inv_name, entity.target_name()),
+        ));
+        return;
+    }
+    if let Some(other) = inv.inverse.as_ref().filter(|&other| other != name) {
+        errors.push(Error::new(
+            inv_name.span(),
+            format!("`{}.{}` is declared as the inverse of `{}`", dst.name, inv_name, other),
+        ));
+        return;
+    }
+    if !rel.is_stored(store) && rel.delete_rule.is_some() {
+        errors.push(Error::new(
+            name.span(),
+            format!("the delete rule must be declared on `{}.{}`, which stores the relationship", dst.name, inv_name),
+        ));
+    }
+
+    // check each pair once, on the relationship declared last
+    if store.rel_position(dst, inv) > store.rel_position(entity, rel) {
+        return;
+    }
+    let msg = match (rel.multiplicity, inv.multiplicity) {
+        (Many, Many) if rel.ordered && inv.ordered => Some("both sides of a many-to-many relationship cannot be ordered"),
+        (Many, Many) => None,
+        (Many, _) | (_, Many) => {
+            let (to_many, to_one) = if rel.multiplicity == Many { (rel, inv) } else { (inv, rel) };
+            if to_many.ordered {
+                Some("the inverse of an ordered relationship must be an unordered to-many relationship")
+            } else if to_one.unique {
+                Some("the inverse of a unique relationship must be optional (`?`)")
+            } else {
+                None
+            }
+        }
+        _ => match (rel.unique, inv.unique) {
+            (false, false) => Some("one side of a one-to-one relationship must be `unique`"),
+            (true, true) => Some("only one side of a one-to-one relationship can be `unique`"),
+            (true, false) if inv.multiplicity == One => Some("the inverse of a unique relationship must be optional (`?`)"),
+            (false, true) if rel.multiplicity == One => Some("the inverse of a unique relationship must be optional (`?`)"),
+            _ => None,
+        },
+    };
+    if let Some(msg) = msg {
+        errors.push(Error::new(name.span(), msg));
+    }
+}
+
+/// Checks the restrictions on the extensions of entities of the base store.
+fn validate_extensions(store: &Store) -> Result<(), Error> {
+    for (i, ext) in store.extensions().enumerate() {
+        let base = ext.target_name();
+        if store.base.is_none() {
+            return Err(Error::new(
+                base.span(),
+                "extensions can only be declared in a store that extends another store (`store Name : Base;`)",
+            ));
+        }
+        if store.is_local(base) || store.extensions().take(i).any(|e| e.target_name() == base) {
+            return Err(Error::new(base.span(), format!("`{}` is already declared in this store", base)));
+        }
+        if let Some(cluster) = &ext.cluster {
+            return Err(Error::new(cluster.span(), "extensions cannot be clustered"));
+        }
+        if let Some(supertype) = &ext.supertype {
+            return Err(Error::new(supertype.span(), "extensions cannot be subtypes of abstract entities"));
+        }
+        // the attributes are forwarded to the extension trait
+        if let Some(attr) = ext.attrs.iter().find(|attr| !TRAIT_ATTRIBUTES.iter().any(|name| attr.path().is_ident(name))) {
+            return Err(Error::new_spanned(attr, "extensions are traits, only doc comments and lint attributes can be used"));
+        }
+        if let Some(attr) = ext.id_attrs.first() {
+            return Err(Error::new(attr.span(), "extensions have no key type of their own, `#[id]` cannot be used"));
+        }
+        if let Some(attr) = ext.attrs().find(|attr| attr.indexed || attr.unique) {
+            return Err(Error::new(attr.name.span(), "attributes of extensions cannot be indexed or unique"));
+        }
+        if !ext.checks.is_empty() {
+            return Err(Error::new(
+                ext.name.span(),
+                "extensions cannot have row constraints, only constraints on their attributes",
+            ));
+        }
+        if let Some(attr) = ext.attrs().find(|attr| attr.readonly) {
+            return Err(Error::new(
+                attr.name.span(),
+                "attributes of extensions are set after insertion, they cannot be read-only",
+            ));
+        }
+        if let Some(attr) = ext.attrs().find(|attr| !matches!(attr.vis, Visibility::Inherited) || attr.set_vis.is_some()) {
+            return Err(Error::new(
+                attr.name.span(),
+                "attributes of extensions are accessed through the extension trait, they cannot have a visibility",
+            ));
+        }
+        if let Some(attr) = ext.attrs().find(|attr| attr.default.is_some()) {
+            return Err(Error::new(attr.name.span(), "attributes of extensions are optional, they have no default value"));
+        }
+        for rel in ext.rels() {
+            if rel.multiplicity == One {
+                // existing entities of the base store wouldn't have a value
+                return Err(Error::new(
+                    rel.name.span(),
+                    "relationships of extensions must be optional (`?`) or to-many (`*`)",
+                ));
+            }
+            if rel.delete_rule() == DeleteRule::Cascade {
+                return Err(Error::new(
+                    rel.name.span(),
+                    "deletes cannot cascade to the entities of the base store",
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Generates the extension trait of an entity of the base store (e.g. `AlbumExt` for `AlbumExt[Album]`).
+///
+/// Attributes and relationships of extensions are optional, and stored in maps keyed by the entity of the base
+/// store. The methods to access them are implemented on the key type of the extended entity.
+fn generate_extension(store: &Store, ext: &Entity) -> Result<TokenStream, Error> {
+    let name = &ext.name;
+    let attrs = &ext.attrs;
+    let key = ext.key_ty();
+    let store_ty = store.store_type();
+    let err = quote!(#CRATE::Error);
+    let vis = &store.vis;
+    let db_name = &store.name;
+    let get_store = quote!(#CRATE::HasStore::<#store_ty>::store);
+    let get_store_mut = quote!(#CRATE::HasStore::<#store_ty>::store_mut);
+    let exists = store.contains(ext.target_name(), quote!(db), quote!(self));
+
+    let mut methods = vec![];
+
+    // Attributes
+    for attr in ext.attrs() {
+        let getter = &attr.name;
+        let setter = format_ident!("set_{}", attr.name);
+        let field = ext.extension_field(&attr.name);
+        let ty = &attr.ty;
+        let checks = attr.generate_checks(ext, quote!(&value));
+        let log_removed = store.log_value(name, getter, true, "Removed", quote!(self), quote!(&prev));
+        let log_inserted = store.log_value(name, getter, true, "Inserted", quote!(self), quote!(&value));
+        methods.push(Method {
+            attrs: attr.attrs.clone(),
+            sig: quote!(fn #getter <DB: ?Sized + #db_name> (self, db: &DB) -> Option<&#ty>),
+            body: quote!(#get_store(db).#field.get(&self)),
+        });
+        methods.push(Method {
+            attrs: vec![],
+            sig: quote!(fn #setter <DB: ?Sized + #db_name> (self, db: &mut DB, value: Option<#ty>) -> Result<(),#err>),
+            body: quote! {
+                if !#exists {
+                    return Err(#err::EntityNotFound);
+                }
+                #checks
+                let store = #get_store_mut(db);
+                let prev = match &value {
+                    Some(value) => store.#field.insert(self, ::std::clone::Clone::clone(value)),
+                    None => store.#field.remove(&self),
+                };
+                store.changes.begin_operation();
+                #log_removed
+                #log_inserted
+                Ok(())
+            },
+        });
+    }
+
+    // Foreign keys
+    for rel in ext.foreign_keys(store) {
+        let getter = &rel.name;
+        let setter = format_ident!("set_{}", rel.name);
+        let field = ext.extension_field(&rel.name);
+        let index = rel.index_field(ext);
+        let dst = rel.destination_key(store)?;
+        let dst_exists = store.contains(&rel.destination, quote!(db), quote!(fk));
+        let log_removed = store.log_change(name, Some(getter), "Removed", quote!(self, prev_fk));
+        let log_inserted = store.log_change(name, Some(getter), "Inserted", quote!(self, fk));
+        let (check_unique, remove_entry, insert_entry) = if rel.unique {
+            (
+                quote! {
+                    if let Some(fk) = fk {
+                        if store.#index.get(&fk).map_or(false, |&src| src != self) {
+                            return Err(#err::RelationshipTooManyTargets);
+                        }
+                    }
+                },
+                quote!(store.#index.remove(&prev_fk);),
+                quote!(store.#index.insert(fk, self);),
+            )
+        } else {
+            (
+                quote!(),
+                quote!(store.#index.remove(&(prev_fk, self));),
+                quote!(store.#index.insert((fk, self), ());),
+            )
+        };
+        methods.push(Method {
+            attrs: rel.attrs.clone(),
+            sig: quote!(fn #getter <DB: ?Sized + #db_name> (self, db: &DB) -> Option<#dst>),
+            body: quote!(#get_store(db).#field.get(&self).copied()),
+        });
+        methods.push(Method {
+            attrs: vec![],
+            sig: quote!(fn #setter <DB: ?Sized + #db_name> (self, db: &mut DB, fk: Option<#dst>) -> Result<(),#err>),
+            body: quote! {
+                if !#exists {
+                    return Err(#err::EntityNotFound);
+                }
+                if let Some(fk) = fk {
+                    if !#dst_exists {
+                        return Err(#err::ForeignKeyViolation);
+                    }
+                }
+                let store = #get_store_mut(db);
+                #check_unique
+                let prev_fk = match fk {
+                    Some(fk) => store.#field.insert(self, fk),
+                    None => store.#field.remove(&self),
+                };
+                store.changes.begin_operation();
+                if let Some(prev_fk) = prev_fk {
+                    #remove_entry
+                    #log_removed
+                }
+                if let Some(fk) = fk {
+                    #insert_entry
+                    #log_inserted
+                }
+                Ok(())
+            },
+        });
+    }
+
+    // Many-to-many relationships
+    for rel in ext.rels().filter(|r| r.is_junction(store)) {
+        methods.extend(generate_junction_methods(store, ext, rel)?);
+    }
+
+    // Ordered relationships
+    for rel in ext.rels().filter(|r| r.ordered) {
+        methods.extend(generate_list_methods(store, ext, rel)?);
+    }
+
+    // Inverse navigation of the relationships that point to the extended entity
+    for entity in store.entities.iter() {
+        for rel in entity.rels().filter(|r| &r.destination == ext.target_name() && r.is_stored(store)) {
+            methods.extend(generate_inverse_getter(store, entity, rel)?);
         }
-    };
+    }
 
-    let res = quote! {
-        #[derive(Clone)]
-        #vis struct #ent {
-            id: #key,
-            #(#fields,)*
+    let inverse_getters = generate_inverse_getters(store, ext)?;
+    let decls = methods.iter().map(Method::trait_decl);
+    let impls = methods.iter().map(Method::trait_impl);
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis trait #name {
+            #(#decls)*
         }
 
-        impl ::std::ops::Index<#key> for #store_ty {
-            type Output = #ent;
-            fn index(&self, key: #key) -> &Self::Output {
-                &self.#ent[key]
+        impl #name for #key {
+            #(#impls)*
+        }
+
+        #inverse_getters
+    })
+}
+
+/// Generates the `StoreExtension` impl of an extension store, which applies its delete rules when entities are
+/// removed from the base store.
+///
+/// Databases call it from the `before_remove` and `after_remove` hooks of the base store.
+fn generate_base_remove_hooks(store: &Store) -> Result<TokenStream, Error> {
+    let (Some(base_store_ty), Some(base_removal_ty)) = (store.base_type("Store"), store.base_type("Removal")) else {
+        return Ok(quote!());
+    };
+    let store_ty = store.store_type();
+    let removal_ty = store.removal_type();
+    let err = quote!(#CRATE::Error);
+
+    let mut before_remove = TokenStream::new();
+    let mut after_remove = TokenStream::new();
+    for base_ent in store.base_entities() {
+        let mut check = TokenStream::new();
+        let mut cleanup = TokenStream::new();
+
+        // data of the extension of the removed entity
+        if let Ok(ext) = store.entity_by_name(base_ent) {
+            for attr in ext.attrs() {
+                let field = ext.extension_field(&attr.name);
+                let log = store.log_change(&ext.name, Some(&attr.name), "Removed", quote!(id, value));
+                cleanup.append_all(quote! {
+                    if let Some(value) = store.#field.remove(&id) {
+                        #log
+                    }
+                });
+            }
+            for rel in ext.foreign_keys(store) {
+                let field = ext.extension_field(&rel.name);
+                let index = rel.index_field(ext);
+                let remove_entry = if rel.unique {
+                    quote!(store.#index.remove(&dst);)
+                } else {
+                    quote!(store.#index.remove(&(dst, id));)
+                };
+                let log = store.log_change(&ext.name, Some(&rel.name), "Removed", quote!(id, dst));
+                cleanup.append_all(quote! {
+                    if let Some(dst) = store.#field.remove(&id) {
+                        #remove_entry
+                        #log
+                    }
+                });
             }
         }
+        cleanup.append_all(generate_junction_cleanup(store, base_ent)?);
 
-        impl ::std::ops::IndexMut<#key> for #store_ty {
-            fn index_mut(&mut self, key: #key) -> &mut Self::Output {
-                &mut self.#ent[key]
+        // references to the removed entity
+        for (fk_ent, fk_rel) in store.foreign_key_refs(base_ent) {
+            let src_ent = &fk_ent.name;
+            let sources = fk_rel.sources_of(fk_ent, store, quote!(store), quote!(id));
+            match fk_rel.delete_rule() {
+                DeleteRule::Deny => {
+                    check.append_all(quote! {
+                        if #sources.next().is_some() {
+                            return Err(#err::RelationshipDeniedDelete);
+                        }
+                    });
+                }
+                DeleteRule::Cascade => {
+                    check.append_all(quote! {
+                        for src in #sources {
+                            #src_ent::before_remove(store, src, &mut removing)?;
+                        }
+                    });
+                }
+                DeleteRule::Nullify => {}
+            }
+            if fk_rel.multiplicity != Many {
+                // only extensions have foreign keys to the entities of the base store
+                let src = fk_ent.key_ty();
+                let field = fk_ent.extension_field(&fk_rel.name);
+                let index = fk_rel.index_field(fk_ent);
+                let remove_entry = if fk_rel.unique {
+                    quote!(store.#index.remove(&id);)
+                } else {
+                    quote!(store.#index.remove(&(id, src));)
+                };
+                let log = store.log_change(src_ent, Some(&fk_rel.name), "Removed", quote!(src, id));
+                cleanup.append_all(quote! {
+                    let sources: Vec<#src> = #sources.collect();
+                    for src in sources {
+                        store.#field.remove(&src);
+                        #remove_entry
+                        #log
+                    }
+                });
             }
         }
 
-        impl #CRATE::EntityStore<#ent> for #store_ty {
-            #insert_method
-            #remove_method
+        if !check.is_empty() {
+            before_remove.append_all(quote! {
+                for &id in base.#base_ent.iter() {
+                    #check
+                }
+            });
+        }
+        if !cleanup.is_empty() {
+            after_remove.append_all(quote! {
+                for &id in base.#base_ent.iter() {
+                    #cleanup
+                }
+            });
+        }
+    }
 
-            fn remove(&mut self, s: #key) -> Result<#ent, #err> {
-                todo!()
+    Ok(quote! {
+        impl #CRATE::StoreExtension<#base_store_ty> for #store_ty {
+            #[allow(unused_mut, unused_variables)]
+            fn before_base_remove(&self, base: &#base_removal_ty) -> Result<#removal_ty, #err> {
+                let store = self;
+                let mut removing = #removal_ty::default();
+                #before_remove
+                Ok(removing)
             }
 
-            fn delta<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = #CRATE::Delta<&'a #ent>> + 'a {
-                self.#ent.delta(&other.#ent)
+            fn after_base_remove(&mut self, base: &#base_removal_ty) -> #removal_ty {
+                // errors have been reported by `before_base_remove`
+                let removing = #CRATE::StoreExtension::<#base_store_ty>::before_base_remove(self, base).unwrap_or_default();
+                let store = self;
+                store.changes.begin_operation();
+                #after_remove
+                store.remove_all(&removing);
+                removing
             }
+        }
+    })
+}
 
-            fn iter<'a>(&'a self) -> impl Iterator<Item = &'a #ent> + 'a {
-                self.#ent.iter()
+/// Returns the type as written in the schema, spaced the way rustfmt formats it (e.g. `Option<&'static str>`) rather
+/// than with the spaces that `to_string` inserts between all tokens.
+fn type_name(ty: &syn::Type) -> String {
+    let mut name = String::new();
+    write_tokens(ty.to_token_stream(), &mut name);
+    name
+}
+
+fn write_tokens(tokens: TokenStream, out: &mut String) {
+    let is_word = |token: &TokenTree| matches!(token, TokenTree::Ident(_) | TokenTree::Literal(_));
+    let is_punct = |token: &TokenTree, c: char| matches!(token, TokenTree::Punct(p) if p.as_char() == c);
+    let mut prev: Option<TokenTree> = None;
+    // whether `prev` is the `>` of `->`
+    let mut after_arrow = false;
+    for token in tokens {
+        if let Some(prev) = &prev {
+            let space = (is_word(prev) && is_word(&token))
+                || is_punct(prev, ',')
+                || is_punct(prev, ';')
+                || [prev, &token].into_iter().any(|t| is_punct(t, '+') || is_punct(t, '='))
+                || is_punct(&token, '-')
+                || after_arrow;
+            if space {
+                out.push(' ');
             }
+            after_arrow = is_punct(prev, '-') && is_punct(&token, '>');
         }
-
-        impl #ent {
-            #vis fn all <DB: ?Sized + #db_name> (db: &DB) -> impl Iterator<Item = &#ent> + '_ {
-                db.store().#ent.values()
+        match &token {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::None => ("", ""),
+                };
+                out.push_str(open);
+                write_tokens(group.stream(), out);
+                out.push_str(close);
             }
+            token => out.push_str(&token.to_string()),
         }
+        prev = Some(token);
+    }
+}
 
-        #[derive(Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Debug)]
-        #[repr(transparent)]
-        #vis struct #key(::std::num::NonZeroU32);
-
-        impl #key {
-            #vis const MIN: Self = Self(::std::num::NonZeroU32::MIN);
-            #vis const MAX: Self = Self(::std::num::NonZeroU32::MAX);
-
-            #(#attr_getters)*
-            #(#attr_setters)*
-            #(#fk_setters)*
+/// Generates the description of the store returned by `Store::SCHEMA`.
+fn generate_schema(store: &Store) -> TokenStream {
+    let name = store.name.to_string();
+    let version = match store.version {
+        Some((version, _)) => quote!(Some(#version)),
+        None => quote!(None),
+    };
+    let base = match store.base_type("Store") {
+        Some(base) => quote!(Some(<#base as #CRATE::Store>::SCHEMA)),
+        None => quote!(None),
+    };
+    let option = |value: Option<&Ident>| match value {
+        Some(value) => {
+            let value = value.to_string();
+            quote!(Some(#value))
         }
+        None => quote!(None),
+    };
 
-        impl #CRATE::EntityId for #key {
-            type Entity = #ent;
+    let mut entities = vec![];
+    let mut tables = 0u32;
+    for entity in store.entities.iter() {
+        // only the entities stored in tables of their own are numbered, in declaration order
+        let table = if entity.extends.is_none() && !entity.is_abstract {
+            let table = tables;
+            tables += 1;
+            quote!(Some(#CRATE::TableIndex::from_u32(#table)))
+        } else {
+            quote!(None)
+        };
+        let ent_name = entity.name.to_string();
+        let keys = entity.keys.iter().map(|key| key.to_string());
+        let is_abstract = entity.is_abstract;
+        let supertype = option(entity.supertype.as_ref());
+        let extends = option(entity.extends.as_ref());
 
-            fn to_u32(self) -> u32 {
-                self.0.get() - 1
+        let attributes = entity.attrs().map(|attr| {
+            let name = attr.name.to_string();
+            let type_name = type_name(&attr.ty);
+            let (optional, unique, indexed, readonly) = (attr.optional, attr.unique, attr.indexed, attr.readonly);
+            quote! {
+                #CRATE::schema::AttributeSchema {
+                    name: #name,
+                    type_name: #type_name,
+                    optional: #optional,
+                    unique: #unique,
+                    indexed: #indexed,
+                    readonly: #readonly,
+                }
             }
+        });
 
-            fn from_u32(i: u32) -> Self {
-                Self(unsafe { ::std::num::NonZeroU32::new_unchecked(i + 1) })
+        let relations = entity.rels().map(|rel| {
+            let name = rel.name.to_string();
+            let destination = rel.destination.to_string();
+            let multiplicity = match rel.multiplicity {
+                ZeroOrOne => quote!(ZeroOrOne),
+                One => quote!(One),
+                Many => quote!(Many),
+            };
+            let delete_rule = match rel.delete_rule() {
+                DeleteRule::Deny => quote!(Deny),
+                DeleteRule::Nullify => quote!(Nullify),
+                DeleteRule::Cascade => quote!(Cascade),
+            };
+            let (ordered, unique) = (rel.ordered, rel.unique);
+            let inverse = option(rel.inverse.as_ref());
+            let stored = rel.is_stored(store);
+            quote! {
+                #CRATE::schema::RelationSchema {
+                    name: #name,
+                    destination: #destination,
+                    multiplicity: #CRATE::schema::Multiplicity::#multiplicity,
+                    ordered: #ordered,
+                    unique: #unique,
+                    inverse: #inverse,
+                    delete_rule: #CRATE::schema::DeleteRule::#delete_rule,
+                    stored: #stored,
+                }
             }
-        }
+        });
 
-        impl #CRATE::Entity for #ent {
-            type Id = #key;
-            type Store = #store_ty;
-            fn id(&self) -> Self::Id {
-                self.id
+        entities.push(quote! {
+            #CRATE::schema::EntitySchema {
+                name: #ent_name,
+                table: #table,
+                key: &[#(#keys),*],
+                is_abstract: #is_abstract,
+                supertype: #supertype,
+                extends: #extends,
+                attributes: &[#(#attributes),*],
+                relations: &[#(#relations),*],
             }
-        }
-    };
+        });
+    }
 
-    Ok(res)
+    quote! {
+        &#CRATE::StoreSchema {
+            name: #name,
+            version: #version,
+            base: #base,
+            entities: &[#(#entities),*],
+        }
+    }
 }
 
-/*
-/// Generates the method (a tuple `(signature, implementation)`) that sets the specified foreign key attribute.
-///
-/// # Example
-///
-/// For `rel album: Album` in `Track`:
-/// ```ignore
-/// fn set_track_album(&mut self, src: Track, dst: Album) -> Result<(), Error>;
-/// ```
+/// Generates the `Field` impls of the key types of a versioned store, and the methods that export and import its data.
 ///
-fn generate_set_foreign_key_method(
-    store: &Store,
-    src: &Entity,
-    rel: &Rel,
-    out_tokens: &mut TokenStream,
-) -> Result<(), Error>
-{
-    let inv_rel = rel.inverse(store);
-    let rd = &rel.name;
-    use Multiplicity::*;
-
-    let store_name = format_ident!("{}Store", store.name);
-    let db_name = &store.name;
-
-    let rel_verb = match rel.multiplicity {
-        One | ZeroOrOne => "set",
-        Many => "add",
-    };
-    let rel_name = format_ident!(
-        "{}_{}_{}",
-        rel_verb,
-        src.name.to_string().to_lowercase(),
-        rel.name.to_string().to_lowercase()
-    );
-    let t_src = &src.name;
-    let t_dst = if rel.is_optional_one() {
-        let ty = &rel.destination;
-        quote!(Option<#ty>)
-    } else {
-        let ty = &rel.destination;
-        quote!(#ty)
+/// Imported rows are stored without checks, then the indices are rebuilt from them with the statements that
+/// maintain them on insertion, which also check all constraints.
+fn generate_migration(store: &Store) -> Result<TokenStream, Error> {
+    let Some((version, span)) = store.version else {
+        return Ok(quote!());
     };
+    if store.base.is_some() {
+        return Err(Error::new(span, "stores that extend another store cannot be versioned"));
+    }
+    let store_ty = store.store_type();
+    let err = quote!(#CRATE::Error);
+    let vis = &store.vis;
+    let migration = quote!(#CRATE::migration);
 
-    out_tokens.append_all(quote! {
-        impl #store_name {
-            fn #rel_name (&mut self, s: #t_src, fk: #t_dst) -> Result<(), #CRATE::Error> {
-
-                // FK index is (FK,S) -> () for regular foreign keys
-                //             FK -> S for keys with `unique` constraint
+    // Conversions of the key types
+    let mut key_impls = TokenStream::new();
+    for entity in store.tables() {
+        let key = entity.key_ty();
+        let (to_value, from_value) = if entity.has_composite_key() {
+            let fields = (0..entity.keys.len()).map(syn::Index::from);
+            let components = entity.keys.iter().map(|_| quote!(#migration::Field::from_value(values.next()?)?));
+            (
+                quote!(#migration::Value::List(vec![#(#migration::Field::to_value(&self.#fields)),*])),
+                quote! {
+                    match value {
+                        #migration::Value::List(values) => {
+                            let mut values = values.into_iter();
+                            let key = Self(#(#components),*);
+                            values.next().is_none().then_some(key)
+                        }
+                        _ => None,
+                    }
+                },
+            )
+        } else {
+            (
+                quote!(#migration::Value::Id(#CRATE::EntityId::to_u32(*self))),
+                quote! {
+                    match value {
+                        // the key stores `id + 1`
+                        #migration::Value::Id(id) => id.checked_add(1).map(|_| #CRATE::EntityId::from_u32(id)),
+                        _ => None,
+                    }
+                },
+            )
+        };
+        key_impls.append_all(quote! {
+            impl #migration::Field for #key {
+                fn to_value(&self) -> #migration::Value {
+                    #to_value
+                }
 
-                //self.#fk_index
-                let prev_fk = ::std::mem::replace(&mut self[s].#fk, fk);
-                // update indices
-                self.#fk_index.remove(s,prev_fk);
-                self.#fk_index.insert(s,fk);
+                fn from_value(value: #migration::Value) -> Option<Self> {
+                    #from_value
+                }
+            }
+        });
+    }
+    for entity in store.abstracts() {
+        let key = entity.key_ty();
+        let subtypes: Vec<_> = store.subtypes(&entity.name).map(|e| &e.name).collect();
+        let tags: Vec<_> = subtypes.iter().map(|subtype| subtype.to_string()).collect();
+        key_impls.append_all(quote! {
+            impl #migration::Field for #key {
+                fn to_value(&self) -> #migration::Value {
+                    match self {
+                        #(
+                            Self::#subtypes(id) => #migration::Value::Tagged(
+                                #tags.to_string(),
+                                Box::new(#migration::Field::to_value(id)),
+                            ),
+                        )*
+                    }
+                }
 
-                Ok(())
+                fn from_value(value: #migration::Value) -> Option<Self> {
+                    match value {
+                        #migration::Value::Tagged(tag, value) => match tag.as_str() {
+                            #(#tags => Some(Self::#subtypes(#migration::Field::from_value(*value)?)),)*
+                            _ => None,
+                        },
+                        _ => None,
+                    }
+                }
             }
+        });
+    }
+
+    let mut export = TokenStream::new();
+    let mut known_fields = vec![];
+    let mut import_rows = TokenStream::new();
+    let mut import_indices = TokenStream::new();
+    for entity in store.tables() {
+        let ent = &entity.name;
+        let ent_name = ent.to_string();
+        let fields: Vec<&Ident> = entity
+            .attrs()
+            .map(|attr| &attr.name)
+            .chain(entity.foreign_keys(store).map(|rel| &rel.name))
+            .collect();
+        let field_names: Vec<_> = fields.iter().map(|name| name.to_string()).collect();
+        let mut known = field_names.clone();
+
+        // to-many relationships are exported in the rows of their sources
+        let mut export_rels = TokenStream::new();
+        for (_, rel) in store.junctions().into_iter().filter(|(e, _)| e.name == *ent) {
+            let name = rel.name.to_string();
+            known.push(name.clone());
+            let (fwd, _) = rel.junction_fields(entity, store);
+            let dst = rel.destination_key(store)?;
+            export_rels.append_all(quote! {
+                let dsts = self.#fwd.range((row.id, <#dst>::MIN)..=(row.id, <#dst>::MAX));
+                let dsts = dsts.map(|((_, dst), _)| #migration::Field::to_value(dst)).collect();
+                fields.insert(#name.to_string(), #migration::Value::List(dsts));
+            });
+        }
+        for rel in entity.rels().filter(|rel| rel.ordered) {
+            let name = rel.name.to_string();
+            known.push(name.clone());
+            let list = rel.list_field(entity);
+            export_rels.append_all(quote! {
+                let dsts = self.#list.iter(&row.id).map(#migration::Field::to_value).collect();
+                fields.insert(#name.to_string(), #migration::Value::List(dsts));
+            });
         }
-    });
+        export.append_all(quote! {
+            let rows = data.rows_mut(#ent_name);
+            for row in self.#ent.values() {
+                let mut fields = #migration::Row::new();
+                fields.insert("id".to_string(), #migration::Field::to_value(&row.id));
+                #(fields.insert(#field_names.to_string(), #migration::Field::to_value(&row.#fields));)*
+                #export_rels
+                rows.push(fields);
+            }
+        });
+        known_fields.push(quote!(#ent_name => (#ent_name, &["id", #(#known),*])));
 
-    Ok(())
-}*/
+        let store_row = entity.store_row(quote!(data));
+        import_rows.append_all(quote! {
+            for row in input.rows(#ent_name) {
+                let data = #ent {
+                    id: #migration::field(row, #ent_name, "id")?,
+                    #(#fields: #migration::field(row, #ent_name, #field_names)?,)*
+                };
+                if !#store_row {
+                    return Err(#err::DuplicateKey);
+                }
+            }
+        });
 
-/*fn generate_rel_impls(store: &Store, out_tokens: &mut TokenStream) {
-    for entity in store.entities.iter() {
-        for rel in entity.rels() {
-            let rel_ty_name = format_ident!("Rel_{}_{}", entity.name, rel.name);
-            let inv_rel_ty_name = format_ident!("Rel_{}_{}_inv", entity.name, rel.name);
-            let index_name = format_ident!("index_{}_{}", entity.name, rel.name);
-            let rel_src = &entity.name;
-            let rel_dst = &rel.destination;
-            let rel_fk = &rel.name;
-            let rel_kind = match rel.multiplicity {
-                One => quote!(index_N_to_1),
-                _ => unimplemented!(),
-            };
-            out_tokens.append_all(quote! {
-                #CRATE::#rel_kind!(#rel_ty_name, #rel_src, #rel_fk, #rel_dst, #inv_rel_ty_name, #index_name);
+        let (checks, update_indices) = generate_insert_checks(store, entity);
+        import_indices.append_all(quote! {
+            let rows: Vec<#ent> = self.#ent.values().cloned().collect();
+            for data in rows {
+                let id = data.id;
+                #checks
+                #update_indices
+            }
+        });
+        for rel in entity.foreign_keys(store).filter(|rel| rel.is_hierarchy(entity)) {
+            let fk = &rel.name;
+            import_indices.append_all(quote! {
+                let len = self.#ent.len();
+                for data in self.#ent.values() {
+                    let ancestors = ::std::iter::successors(data.#fk, |&id| self.#ent[id].#fk);
+                    if ancestors.take(len).any(|id| id == data.id) {
+                        return Err(#err::CycleDetected);
+                    }
+                }
             });
         }
     }
-}*/
+
+    // to-many relationships, once all entities are imported
+    let mut import_rels = TokenStream::new();
+    for (entity, rel) in store.junctions() {
+        let (ent_name, name) = (entity.name.to_string(), rel.name.to_string());
+        let (fwd, bwd) = rel.junction_fields(entity, store);
+        let key = entity.key_ty();
+        let dst = rel.destination_key(store)?;
+        let dst_exists = store.contains_in(&rel.destination, quote!(self), quote!(dst));
+        import_rels.append_all(quote! {
+            for row in input.rows(#ent_name) {
+                let id: #key = #migration::field(row, #ent_name, "id")?;
+                let dsts: Option<Vec<#dst>> = #migration::field(row, #ent_name, #name)?;
+                for dst in dsts.unwrap_or_default() {
+                    if !#dst_exists {
+                        return Err(#err::ForeignKeyViolation);
+                    }
+                    self.#fwd.insert((id, dst), ());
+                    self.#bwd.insert((dst, id), ());
+                }
+            }
+        });
+    }
+    for (entity, rel) in store.lists() {
+        let (ent_name, name) = (entity.name.to_string(), rel.name.to_string());
+        let list = rel.list_field(entity);
+        let key = entity.key_ty();
+        let dst = rel.destination_key(store)?;
+        let dst_exists = store.contains_in(&rel.destination, quote!(self), quote!(dst));
+        import_rels.append_all(quote! {
+            for row in input.rows(#ent_name) {
+                let id: #key = #migration::field(row, #ent_name, "id")?;
+                let dsts: Option<Vec<#dst>> = #migration::field(row, #ent_name, #name)?;
+                for (index, dst) in dsts.unwrap_or_default().into_iter().enumerate() {
+                    if !#dst_exists {
+                        return Err(#err::ForeignKeyViolation);
+                    }
+                    self.#list.insert_at(id, index, dst);
+                }
+            }
+        });
+    }
+
+    Ok(quote! {
+        #key_impls
+
+        impl #store_ty {
+            /// The version of the schema of the store.
+            #vis const VERSION: u32 = #version;
+
+            /// Exports the data of the store, to be imported by this or a later version of the schema.
+            #vis fn export(&self) -> #migration::StoreData {
+                let mut data = #migration::StoreData::new(#version);
+                #export
+                data
+            }
+
+            /// Imports data exported by this or an earlier version of the schema, upgraded with the given migrations.
+            ///
+            /// The indices are rebuilt from the migrated data, and all constraints are checked.
+            #vis fn import(mut input: #migration::StoreData, migrations: &#migration::Migrations) -> Result<Self, #err> {
+                migrations.migrate(&mut input, #version)?;
+                let mut store = Self::default();
+                store.import_rows(&input)?;
+                Ok(store)
+            }
+
+            fn import_rows(&mut self, input: &#migration::StoreData) -> Result<(), #err> {
+                // data that is not part of this version of the schema would be lost
+                for (name, rows) in &input.entities {
+                    let (entity, fields): (&'static str, &[&str]) = match name.as_str() {
+                        #(#known_fields,)*
+                        _ if rows.is_empty() => continue,
+                        _ => return Err(#err::UnknownEntity { entity: name.clone() }),
+                    };
+                    for row in rows {
+                        if let Some(attribute) = row.keys().find(|attribute| !fields.contains(&attribute.as_str())) {
+                            return Err(#err::UnknownAttribute { entity, attribute: attribute.clone() });
+                        }
+                    }
+                }
+                #import_rows
+                #import_indices
+                #import_rels
+                Ok(())
+            }
+        }
+    })
+}
 
 pub(crate) fn generate_store(input: proc_macro::TokenStream) -> syn::Result<TokenStream> {
-    let store: Store = syn::parse(input)?;
+    let mut store: Store = syn::parse(input)?;
+    validate_schema(&store)?;
+    validate_extensions(&store)?;
+    resolve_inheritance(&mut store)?;
+    let store = store;
 
     // name of the wrapper trait (e.g. `MusicDb`)
     let trait_name = &store.name;
@@ -749,67 +3868,249 @@ pub(crate) fn generate_store(input: proc_macro::TokenStream) -> syn::Result<Toke
 
     //let mut impls = TokenStream::new();
 
-
     // generate code for each entity
     let mut entities = vec![];
-    for entity in store.entities.iter() {
+    for entity in store.tables() {
         entities.push(generate_entity(&store, entity)?);
     }
+    for ext in store.extensions() {
+        entities.push(generate_extension(&store, ext)?);
+    }
+    for entity in store.abstracts() {
+        entities.push(generate_abstract(&store, entity)?);
+    }
 
     // Relation impls
     //generate_rel_impls(&store, &mut impls);
 
     // Store fields
     let mut fields = TokenStream::new();
-    for entity in store.entities.iter() {
-        for rel in entity.rels() {
+    for entity in store.entities.iter().filter(|e| !e.is_abstract) {
+        for rel in entity.foreign_keys(&store) {
             let index_name = rel.index_field(entity);
             let rel_src = entity.key_ty();
-            let rel_dst = &rel.destination;
+            let rel_dst = rel.destination_key(&store)?;
             let index_ty = match (rel.multiplicity, rel.unique) {
                 (One | ZeroOrOne, false) => quote!(#CRATE::im::OrdMap<(#rel_dst, #rel_src),()>),
                 (One | ZeroOrOne, true) => quote!(#CRATE::im::OrdMap<#rel_dst, #rel_src>),
-                _ => unimplemented!(),
+                (Many, _) => unreachable!(),
+            };
+            fields.append_all(quote! {
+                #index_name: #index_ty,
+            });
+        }
+        for attr in entity.attrs().filter(|attr| attr.indexed || attr.unique) {
+            let index_name = attr.index_field(entity);
+            let key = entity.key_ty();
+            let ty = &attr.ty;
+            let index_ty = if attr.unique {
+                quote!(#CRATE::im::OrdMap<#ty, #key>)
+            } else {
+                quote!(#CRATE::im::OrdMap<(#ty, #key),()>)
             };
             fields.append_all(quote! {
                 #index_name: #index_ty,
             });
         }
         let name = &entity.name;
+        if entity.extends.is_some() {
+            let key = entity.key_ty();
+            for attr in entity.attrs() {
+                let field = entity.extension_field(&attr.name);
+                let ty = &attr.ty;
+                fields.append_all(quote! {
+                    #field: #CRATE::im::OrdMap<#key, #ty>,
+                });
+            }
+            for rel in entity.foreign_keys(&store) {
+                let field = entity.extension_field(&rel.name);
+                let dst = rel.destination_key(&store)?;
+                fields.append_all(quote! {
+                    #field: #CRATE::im::OrdMap<#key, #dst>,
+                });
+            }
+        } else if entity.has_composite_key() {
+            fields.append_all(quote! {
+                #name: #CRATE::KeyedTable<#name>,
+            });
+        } else if let Some(cluster_key) = entity.cluster_key_ty(&store)? {
+            fields.append_all(quote! {
+                #name: #CRATE::ClusteredTable<#name, #cluster_key>,
+            });
+        } else {
+            fields.append_all(quote! {
+                #name: #CRATE::Table<#name>,
+            });
+        }
+    }
+    for (entity, rel) in store.junctions() {
+        let (fwd, bwd) = rel.junction_fields(entity, &store);
+        let rel_src = entity.key_ty();
+        let rel_dst = rel.destination_key(&store)?;
+        fields.append_all(quote! {
+            #fwd: #CRATE::im::OrdMap<(#rel_src, #rel_dst),()>,
+            #bwd: #CRATE::im::OrdMap<(#rel_dst, #rel_src),()>,
+        });
+    }
+    for (entity, rel) in store.lists() {
+        let list = rel.list_field(entity);
+        let rel_src = entity.key_ty();
+        let rel_dst = rel.destination_key(&store)?;
         fields.append_all(quote! {
-            #name: #CRATE::Table<#name>,
+            #list: #CRATE::ListTable<#rel_src, #rel_dst>,
         });
     }
 
+    // Changes recorded in the change log
+    let change_kind = store.change_kind();
+    let mut changes = TokenStream::new();
+    for entity in store.entities.iter().filter(|e| !e.is_abstract) {
+        let ent = &entity.name;
+        let key = entity.key_ty();
+        let mut push = |member: Option<&Ident>, kinds: &[&str], values: TokenStream| {
+            for kind in kinds {
+                let variant = match member {
+                    Some(member) => format_ident!("{}_{}_{}", ent, member, kind),
+                    None => format_ident!("{}_{}", ent, kind),
+                };
+                changes.append_all(quote!(#variant(#values),));
+            }
+        };
+        if entity.extends.is_none() {
+            push(None, &["Inserted", "Removed"], quote!(#key));
+            if entity.has_unindexed_attrs() {
+                push(None, &["Updated"], quote!(#key));
+            }
+        }
+        for attr in entity.attrs() {
+            let ty = &attr.ty;
+            push(Some(&attr.name), &["Inserted", "Removed"], quote!(#key, #ty));
+        }
+        for rel in entity.rels().filter(|r| r.is_stored(&store)) {
+            let dst = rel.destination_key(&store)?;
+            if rel.ordered {
+                push(Some(&rel.name), &["Inserted", "Removed", "Moved"], quote!(#key, #dst));
+            } else if !rel.is_junction(&store) || !rel.junction_owner(entity, &store).2 {
+                push(Some(&rel.name), &["Inserted", "Removed"], quote!(#key, #dst));
+            }
+        }
+    }
+
     let vis = &store.vis;
     let attrs = &store.attrs;
+
+    // Removal set
+    let removal_name = store.removal_type();
+    let entity_names: Vec<_> = store.tables().map(|e| &e.name).collect();
+    let entity_keys: Vec<_> = store.tables().map(|e| e.key_ty()).collect();
+
+    let base_remove_hooks = generate_base_remove_hooks(&store)?;
+    let schema = generate_schema(&store);
+    let migration = generate_migration(&store)?;
+    let supertraits = match &store.base {
+        Some(base) => quote!(#CRATE::HasStore<#store_name> + #base),
+        None => quote!(#CRATE::HasStore<#store_name>),
+    };
+
     let code = quote! {
         #(#attrs)*
         #[derive(Clone, Default)]
         #[allow(non_snake_case)]
         #vis struct #store_name {
             #fields
+            changes: #CRATE::ChangeLog<#change_kind>,
+        }
+
+        /// A change to the entities of the store, recorded in its change log.
+        ///
+        /// Changes are recorded per attribute and relationship: modifying a value records the removal of the previous
+        /// value, then the insertion of the new one. Absent optional values are not recorded. `update` records the
+        /// modifications of attributes without an index as a single change of the entity (e.g. `Track_Updated`).
+        #[derive(Clone)]
+        #[allow(non_camel_case_types)]
+        #vis enum #change_kind {
+            #changes
+        }
+
+        /// Entities removed by a removal operation on the store, including those removed by `cascade` delete rules.
+        #[derive(Clone, Default)]
+        #[allow(non_snake_case)]
+        #vis struct #removal_name {
+            #(#vis #entity_names: ::std::collections::BTreeSet<#entity_keys>,)*
+        }
+
+        impl #CRATE::Store for #store_name {
+            type Removal = #removal_name;
+            const SCHEMA: &'static #CRATE::StoreSchema = #store_name::SCHEMA;
+
+            fn restore(&mut self, prev: &Self) {
+                let mut changes = ::std::mem::take(&mut self.changes);
+                changes.restore(&prev.changes);
+                *self = ::std::clone::Clone::clone(prev);
+                self.changes = changes;
+            }
         }
 
         impl #store_name {
+            /// Description of the entities and relationships of the store.
+            #vis const SCHEMA: &'static #CRATE::StoreSchema = #schema;
+
             #vis fn new() -> #store_name {
                 Self::default()
             }
+
+            /// Returns the timestamp of the last operation on the store.
+            #vis fn timestamp(&self) -> u64 {
+                self.changes.timestamp()
+            }
+
+            /// Returns the changes made by the operations after the one with the given timestamp, in order.
+            #vis fn changes_since(&self, timestamp: u64) -> impl Iterator<Item = &#change_kind> + '_ {
+                self.changes.since(timestamp)
+            }
+
+            /// Removes all entities in the removal set.
+            fn remove_all(&mut self, removing: &#removal_name) {
+                #(
+                    for &id in removing.#entity_names.iter() {
+                        #entity_names::remove_inner(self, id, removing);
+                    }
+                )*
+            }
         }
 
+        #base_remove_hooks
+
+        #migration
+
         #(#entities)*
 
-        #vis trait #trait_name: #CRATE::HasStore<#store_name> {
+        #vis trait #trait_name: #supertraits {
             fn insert<E: #CRATE::Entity>(&mut self, f: impl FnOnce(E::Id) -> E) -> Result<E::Id, #CRATE::Error> where #store_name: #CRATE::EntityStore<E>;
+            fn insert_with_id<E: #CRATE::Entity>(&mut self, id: E::Id, f: impl FnOnce(E::Id) -> E) -> Result<E::Id, #CRATE::Error> where #store_name: #CRATE::EntityStore<E>;
             fn remove<E: #CRATE::Entity>(&mut self, id: E::Id) -> Result<E, #CRATE::Error> where #store_name: #CRATE::EntityStore<E>;
         }
 
-        impl<DB: ?Sized> #trait_name for DB where DB: #CRATE::HasStore<#store_name> {
+        impl<DB: ?Sized> #trait_name for DB where DB: #supertraits {
             fn insert<E: #CRATE::Entity>(&mut self, f: impl FnOnce(E::Id) -> E) -> Result<E::Id, #CRATE::Error> where #store_name: #CRATE::EntityStore<E> {
-                self.store_mut().insert(f)
+                #CRATE::EntityStore::<E>::insert(#CRATE::HasStore::<#store_name>::store_mut(self), f)
             }
+
+            /// Inserts an entity with the given key (for entities with composite keys, the key must be given).
+            fn insert_with_id<E: #CRATE::Entity>(&mut self, id: E::Id, f: impl FnOnce(E::Id) -> E) -> Result<E::Id, #CRATE::Error> where #store_name: #CRATE::EntityStore<E> {
+                #CRATE::EntityStore::<E>::insert_with_id(#CRATE::HasStore::<#store_name>::store_mut(self), id, f)
+            }
+
+            /// Removes an entity, applying delete rules and the hooks of the database.
             fn remove<E: #CRATE::Entity>(&mut self, id: E::Id) -> Result<E, #CRATE::Error> where #store_name: #CRATE::EntityStore<E> {
-                self.store_mut().remove(id)
+                let removing = #CRATE::EntityStore::<E>::removal(#CRATE::HasStore::<#store_name>::store(self), id)?;
+                #CRATE::HasStore::<#store_name>::before_remove(self, &removing)?;
+                let data = #CRATE::HasStore::<#store_name>::store(self)[id].clone();
+                let store = #CRATE::HasStore::<#store_name>::store_mut(self);
+                store.changes.begin_operation();
+                store.remove_all(&removing);
+                #CRATE::HasStore::<#store_name>::after_remove(self, &removing);
+                Ok(data)
             }
         }
     };
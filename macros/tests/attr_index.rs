@@ -0,0 +1,38 @@
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store ArtistDb;
+
+    Artist(ArtistId) {
+        #[index]
+        name: String,
+        #[index]
+        year: u32,
+    }
+}
+
+common::single_store_db!(ArtistDbStore);
+
+#[test]
+fn attr_index() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let a = db.insert(|id| Artist { id, name: "a".into(), year: 1990 }).unwrap();
+    let b = db.insert(|id| Artist { id, name: "b".into(), year: 1970 }).unwrap();
+    let c = db.insert(|id| Artist { id, name: "a".into(), year: 2000 }).unwrap();
+
+    assert_eq!(Artist::by_name(db, "a").collect::<Vec<_>>(), [a, c]);
+    assert_eq!(Artist::by_name(db, "z").count(), 0);
+    assert_eq!(Artist::range_by_year(db, 1980..).collect::<Vec<_>>(), [a, c]);
+    assert_eq!(Artist::range_by_year(db, ..=1990).collect::<Vec<_>>(), [b, a]);
+
+    c.set_name(db, "b".into()).unwrap();
+    assert_eq!(Artist::by_name(db, "a").collect::<Vec<_>>(), [a]);
+    assert_eq!(Artist::by_name(db, &"b".to_string()).collect::<Vec<_>>(), [b, c]);
+
+    b.remove(db).unwrap();
+    assert_eq!(Artist::by_name(db, "b").collect::<Vec<_>>(), [c]);
+    assert_eq!(Artist::range_by_year(db, ..).collect::<Vec<_>>(), [a, c]);
+}
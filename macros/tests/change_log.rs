@@ -0,0 +1,99 @@
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+        comment: String?,
+        rel tracks: Track*.album,
+    }
+
+    Track(TrackId) {
+        name: String,
+        rel album: Album?.tracks on delete nullify,
+    }
+
+    Playlist(PlaylistId) {
+        rel tracks: Track*,
+        rel ordered: Track[],
+    }
+}
+
+common::single_store_db!(TrackDbStore);
+
+#[test]
+fn change_log() {
+    use TrackDbChangeKind as C;
+    let mut db = Db::default();
+    let db = &mut db;
+    let a = NewAlbum::new("a".into()).insert(db).unwrap();
+    let t = NewTrack { album: Some(a), ..NewTrack::new("t".into()) }.insert(db).unwrap();
+    let changes = db.s.changes_since(0).cloned().collect::<Vec<_>>();
+    assert!(matches!(
+        changes[..],
+        [C::Album_Inserted(a1), C::Album_name_Inserted(a2, ref n), C::Track_Inserted(t1), C::Track_name_Inserted(_, _), C::Track_album_Inserted(t2, a3)]
+            if a1 == a && a2 == a && n == "a" && t1 == t && t2 == t && a3 == a
+    ));
+
+    let ts = db.s.timestamp();
+    a.set_comment(db, Some("c".into())).unwrap();
+    a.set_comment(db, Some("d".into())).unwrap();
+    a.clear_comment(db).unwrap();
+    let changes = db.s.changes_since(ts).cloned().collect::<Vec<_>>();
+    assert!(matches!(
+        changes[..],
+        [
+            C::Album_comment_Inserted(_, ref c1),
+            C::Album_comment_Removed(_, ref c2),
+            C::Album_comment_Inserted(_, ref d1),
+            C::Album_comment_Removed(_, ref d2),
+        ] if c1 == "c" && c2 == "c" && d1 == "d" && d2 == "d"
+    ));
+
+    let p = NewPlaylist::new().insert(db).unwrap();
+    p.add_tracks(db, t).unwrap();
+    p.add_tracks(db, t).unwrap();
+    p.insert_ordered_at(db, 0, t).unwrap();
+    p.insert_ordered_at(db, 0, t).unwrap();
+    p.move_ordered(db, 0, 1).unwrap();
+    let ts = db.s.timestamp();
+    a.remove(db).unwrap();
+    let changes = db.s.changes_since(ts).cloned().collect::<Vec<_>>();
+    assert!(matches!(
+        changes[..],
+        [C::Album_name_Removed(..), C::Album_Removed(_), C::Track_album_Removed(t1, a1)] if t1 == t && a1 == a
+    ));
+    let ts = db.s.timestamp();
+    t.remove(db).unwrap();
+    let changes = db.s.changes_since(ts).cloned().collect::<Vec<_>>();
+    assert!(matches!(
+        changes[..],
+        [
+            C::Track_name_Removed(..),
+            C::Track_Removed(_),
+            C::Playlist_ordered_Removed(..),
+            C::Playlist_ordered_Removed(..),
+            C::Playlist_tracks_Removed(..),
+        ]
+    ));
+    assert_eq!(db.s.changes_since(0).count(), 22);
+}
+
+#[test]
+fn no_changes() {
+    let mut db = Db::default();
+    let db = &mut db;
+    assert_eq!(db.s.changes_since(0).count(), 0);
+    assert_eq!(db.s.changes_since(db.s.timestamp()).count(), 0);
+
+    let a = NewAlbum::new("a".into()).insert(db).unwrap();
+    assert_eq!(db.s.changes_since(db.s.timestamp()).count(), 0);
+    a.set_name(db, "b".into()).unwrap();
+    assert_eq!(db.s.changes_since(db.s.timestamp()).count(), 0);
+    a.remove(db).unwrap();
+    assert_eq!(db.s.changes_since(db.s.timestamp()).count(), 0);
+    assert_eq!(db.s.changes_since(u64::MAX).count(), 0);
+}
@@ -0,0 +1,63 @@
+use kyuudb::Error;
+use kyuudb_macros::store;
+
+mod common;
+
+#[allow(clippy::ptr_arg)]
+fn not_empty(name: &String) -> bool {
+    !name.is_empty()
+}
+
+/// Returns a predicate that captures its argument.
+fn at_most(max: u32) -> impl Fn(&u32) -> bool {
+    move |value| *value <= max
+}
+
+store! {
+    pub store AlbumDb;
+
+    #[check(|album| album.year >= 1900 || album.artist.is_none())]
+    #[check(single, |album| album.tracks <= 100)]
+    Album(AlbumId) {
+        #[check(not_empty)]
+        name: String,
+        #[check(|year| *year <= 2100)]
+        year: u32,
+        #[check(at_most(1000))]
+        tracks: u32,
+        rel artist: Artist?,
+    }
+
+    Artist(ArtistId) {
+        name: String,
+    }
+}
+
+common::single_store_db!(AlbumDbStore);
+
+fn constraint<T>(r: Result<T, Error>) -> &'static str {
+    match r {
+        Err(Error::CheckViolation { constraint }) => constraint,
+        _ => panic!("expected a check violation"),
+    }
+}
+
+#[test]
+fn check() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let ar = NewArtist::new("ar".into()).insert(db).unwrap();
+    assert_eq!(constraint(NewAlbum::new("".into(), 2000, 1).insert(db)), "Album.name");
+    assert_eq!(constraint(NewAlbum::new("a".into(), 3000, 1).insert(db)), "Album.year");
+    assert_eq!(constraint(NewAlbum { artist: Some(ar), ..NewAlbum::new("a".into(), 1800, 1) }.insert(db)), "Album");
+    assert_eq!(constraint(NewAlbum::new("a".into(), 2000, 101).insert(db)), "Album.single");
+    assert_eq!(constraint(NewAlbum::new("a".into(), 2000, 1001).insert(db)), "Album.tracks");
+
+    let a = NewAlbum::new("a".into(), 1800, 1).insert(db).unwrap();
+    assert_eq!(constraint(a.set_artist(db, Some(ar))), "Album");
+    assert_eq!(constraint(a.set_name(db, "".into())), "Album.name");
+    a.set_year(db, 1950).unwrap();
+    a.set_artist(db, Some(ar)).unwrap();
+    assert_eq!(constraint(a.set_year(db, 1850)), "Album");
+    assert_eq!(*a.year(db), 1950);
+}
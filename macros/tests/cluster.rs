@@ -0,0 +1,65 @@
+use kyuudb::{Database, Delta};
+use kyuudb_macros::{database, store};
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+    }
+
+    #[cluster(album, id)]
+    Track(TrackId) {
+        name: String,
+        rel album: Album?.tracks on delete nullify,
+    }
+
+    #[cluster(name, id)]
+    Artist(ArtistId) {
+        name: String,
+    }
+}
+
+database! {
+    pub Db {
+        TrackDb,
+    }
+}
+
+fn names(db: &Db) -> Vec<&str> {
+    Track::all(db).map(|t| t.name.as_str()).collect()
+}
+
+#[test]
+fn cluster() {
+    let mut db = Db::new();
+    let db = &mut db;
+    let a = db.insert(|id| Album { id, name: "a".into() }).unwrap();
+    let b = db.insert(|id| Album { id, name: "b".into() }).unwrap();
+    let t1 = db.insert(|id| Track { id, name: "b1".into(), album: Some(b) }).unwrap();
+    let t2 = db.insert(|id| Track { id, name: "a1".into(), album: Some(a) }).unwrap();
+    let t3 = db.insert(|id| Track { id, name: "b2".into(), album: Some(b) }).unwrap();
+    let t4 = db.insert(|id| Track { id, name: "none".into(), album: None }).unwrap();
+    assert_eq!(names(db), ["none", "a1", "b1", "b2"]);
+
+    let rev = db.snapshot();
+    t1.set_album(db, Some(a)).unwrap();
+    assert_eq!(names(db), ["none", "b1", "a1", "b2"]);
+    assert_eq!(t1.album(db), Some(a));
+    assert_eq!(*t1.name(db), "b1");
+    let deltas: Vec<_> = db.delta::<Track>(rev).unwrap().collect();
+    assert_eq!(deltas.len(), 1);
+    assert!(matches!(deltas[0], Delta::Update { old, new } if old.album == Some(b) && new.album == Some(a)));
+
+    a.remove(db).unwrap();
+    assert_eq!(names(db), ["b1", "a1", "none", "b2"]);
+    assert_eq!(t2.album(db), None);
+    assert_eq!(b.tracks(db).collect::<Vec<_>>(), [t3]);
+    t4.remove(db).unwrap();
+    assert_eq!(names(db), ["b1", "a1", "b2"]);
+
+    let x = db.insert(|id| Artist { id, name: "x".into() }).unwrap();
+    db.insert(|id| Artist { id, name: "y".into() }).unwrap();
+    x.set_name(db, "z".into()).unwrap();
+    assert_eq!(Artist::all(db).map(|a| a.name.as_str()).collect::<Vec<_>>(), ["y", "z"]);
+}
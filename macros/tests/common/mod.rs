@@ -0,0 +1,22 @@
+//! Fixtures shared by the tests of `store!`.
+
+/// Defines `Db`, a database made of a single store.
+macro_rules! single_store_db {
+    ($store:ident) => {
+        #[derive(Clone, Default)]
+        pub struct Db {
+            s: $store,
+        }
+
+        impl kyuudb::HasStore<$store> for Db {
+            fn store(&self) -> &$store {
+                &self.s
+            }
+            fn store_mut(&mut self) -> &mut $store {
+                &mut self.s
+            }
+        }
+    };
+}
+
+pub(crate) use single_store_db;
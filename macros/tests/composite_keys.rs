@@ -0,0 +1,106 @@
+use kyuudb::Error;
+use kyuudb_macros::store;
+
+mod common;
+
+pub type Position = u32;
+
+store! {
+    pub store PlaylistDb;
+
+    Track(TrackId) {
+        name: String,
+    }
+
+    Playlist(PlaylistId) {
+        name: String,
+    }
+
+    PlaylistEntry(PlaylistId, Position) {
+        rel track: Track on delete cascade,
+    }
+
+    ChartEntry(Position, TrackId) {
+        score: u32,
+    }
+
+    Comment(CommentId) {
+        text: String,
+        rel entry: PlaylistEntry? on delete nullify,
+    }
+}
+
+common::single_store_db!(PlaylistDbStore);
+
+#[test]
+fn composite_keys() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let t1 = db.insert(|id| Track { id, name: "t1".into() }).unwrap();
+    let t2 = db.insert(|id| Track { id, name: "t2".into() }).unwrap();
+    let p1 = db.insert(|id| Playlist { id, name: "p1".into() }).unwrap();
+    let p2 = db.insert(|id| Playlist { id, name: "p2".into() }).unwrap();
+
+    for (p, pos, t) in [(p1, 1, t2), (p1, 0, t1), (p2, 0, t2)] {
+        db.insert_with_id(PlaylistEntryId(p, pos), |id| PlaylistEntry { id, track: t }).unwrap();
+    }
+    assert!(matches!(db.insert(|id| PlaylistEntry { id, track: t1 }), Err(Error::KeyRequired)));
+    // the entity must have the key it is inserted with
+    let ts = db.s.timestamp();
+    assert!(matches!(
+        db.insert_with_id(PlaylistEntryId(p2, 1), |_| PlaylistEntry { id: PlaylistEntryId(p2, 2), track: t1 }),
+        Err(Error::KeyMismatch)
+    ));
+    assert!(matches!(db.insert(|_| Track { id: t1, name: "t1".into() }), Err(Error::KeyMismatch)));
+    assert_eq!(db.s.timestamp(), ts);
+    assert_eq!(PlaylistEntry::all(db).count(), 3);
+    assert!(matches!(
+        db.insert_with_id(PlaylistEntryId(p1, 0), |id| PlaylistEntry { id, track: t1 }),
+        Err(Error::DuplicateKey)
+    ));
+
+    let tracks: Vec<_> = PlaylistEntry::range(db, PlaylistEntryId::prefix_1(p1)).map(|e| e.track).collect();
+    assert_eq!(tracks, vec![t1, t2]);
+    assert_eq!(PlaylistEntryId(p2, 0).track(db), t2);
+
+    // foreign key to a composite key
+    let e = PlaylistEntryId(p1, 1);
+    let c = db.insert(|id| Comment { id, text: "c".into(), entry: Some(e) }).unwrap();
+    assert!(matches!(
+        db.insert(|id| Comment { id, text: "c".into(), entry: Some(PlaylistEntryId(p2, 5)) }),
+        Err(Error::ForeignKeyViolation)
+    ));
+
+    // removing t2 cascades to its entries, and nullifies the comment
+    t2.remove(db).unwrap();
+    assert_eq!(PlaylistEntry::all(db).count(), 1);
+    assert_eq!(c.entry(db), None);
+
+    // ids can be chosen for entities with generated ids too
+    let t5 = db.insert_with_id(TrackId(std::num::NonZeroU32::new(5).unwrap()), |id| Track { id, name: "t5".into() }).unwrap();
+    assert!(matches!(db.insert_with_id(t5, |id| Track { id, name: "t5".into() }), Err(Error::DuplicateKey)));
+    let t6 = db.insert(|id| Track { id, name: "t6".into() }).unwrap();
+    assert!(t6 > t5);
+
+    // components of keys that are keys of entities are foreign keys, with a cascade delete rule
+    let p3 = db.insert(|id| Playlist { id, name: "p3".into() }).unwrap();
+    db.insert_with_id(PlaylistEntryId(p3, 0), |id| PlaylistEntry { id, track: t6 }).unwrap();
+    db.insert_with_id(ChartEntryId(1, t6), |id| ChartEntry { id, score: 10 }).unwrap();
+    assert!(matches!(
+        db.insert_with_id(ChartEntryId(2, t2), |id| ChartEntry { id, score: 5 }),
+        Err(Error::ForeignKeyViolation)
+    ));
+    p3.remove(db).unwrap();
+    assert_eq!(PlaylistEntry::range(db, PlaylistEntryId::prefix_1(p3)).count(), 0);
+    assert!(matches!(
+        db.insert_with_id(PlaylistEntryId(p3, 0), |id| PlaylistEntry { id, track: t6 }),
+        Err(Error::ForeignKeyViolation)
+    ));
+    t6.remove(db).unwrap();
+    assert_eq!(ChartEntry::all(db).count(), 0);
+
+    // no id is generated after the largest one
+    let last = TrackId(std::num::NonZeroU32::MAX);
+    db.insert_with_id(last, |id| Track { id, name: "last".into() }).unwrap();
+    assert!(matches!(db.insert(|id| Track { id, name: "t".into() }), Err(Error::IdsExhausted)));
+}
@@ -0,0 +1,119 @@
+use kyuudb::{Database, Delta, Error, HasStore};
+use kyuudb_macros::{database, store};
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+        rel tracks: Track*.album
+    }
+
+    Track(TrackId) {
+        name: String,
+        rel album: Album.tracks on delete cascade
+    }
+}
+
+store! {
+    pub store TagDb;
+
+    Tag(TagId) {
+        name: String
+    }
+
+    TagAlias(TagId, Rank) {
+        name: String
+    }
+}
+
+pub type Rank = u32;
+
+database! {
+    pub AppDb {
+        TrackDb,
+        TagDb,
+    }
+}
+
+#[test]
+fn database() {
+    let mut db = AppDb::new();
+    let db = &mut db;
+    let a = TrackDb::insert(db, |id| Album { id, name: "a".into() }).unwrap();
+    let t = TrackDb::insert(db, |id| Track { id, name: "t".into(), album: a }).unwrap();
+    let rev0 = db.snapshot();
+    let tag = TagDb::insert(db, |id| Tag { id, name: "tag".into() }).unwrap();
+    t.set_name(db, "t2".into()).unwrap();
+    let rev1 = db.snapshot();
+    a.remove(db).unwrap();
+
+    assert_eq!(Track::all(db).count(), 0);
+    assert_eq!(db.delta::<Track>(rev1).unwrap().filter(|d| matches!(d, Delta::Remove(_))).count(), 1);
+    assert_eq!(db.delta::<Tag>(rev0).unwrap().filter(|d| matches!(d, Delta::Insert(_))).count(), 1);
+    assert_eq!(db.delta::<Tag>(rev1).unwrap().count(), 0);
+    assert_eq!(t.name(db.at(rev1).unwrap()), "t2");
+
+    db.rollback(rev1).unwrap();
+    assert_eq!(t.name(db), "t2");
+    assert_eq!(a.tracks(db).collect::<Vec<_>>(), vec![t]);
+    db.rollback(rev0).unwrap();
+    assert_eq!(t.name(db), "t");
+    assert_eq!(Tag::all(db).count(), 0);
+    let _ = tag;
+}
+
+#[test]
+fn stale_revision() {
+    let mut db = AppDb::new();
+    let db = &mut db;
+    let rev0 = db.snapshot();
+    TagDb::insert(db, |id| Tag { id, name: "tag".into() }).unwrap();
+    let rev1 = db.snapshot();
+
+    // rolling back discards the later revisions
+    db.rollback(rev0).unwrap();
+    assert!(db.at(rev1).is_none());
+    assert!(matches!(db.delta::<Tag>(rev1), Err(Error::RevisionNotFound)));
+    assert!(matches!(db.rollback(rev1), Err(Error::RevisionNotFound)));
+    assert_eq!(Tag::all(db).count(), 0);
+
+    // revisions of another database
+    let other = AppDb::new();
+    assert!(other.at(rev0).is_none());
+}
+
+#[test]
+fn reinserted_entity() {
+    let mut db = AppDb::new();
+    let db = &mut db;
+    let tag = TagDb::insert(db, |id| Tag { id, name: "a".into() }).unwrap();
+    let alias = TagAliasId(tag, 0);
+    TagDb::insert_with_id(db, alias, |id| TagAlias { id, name: "a".into() }).unwrap();
+    let rev = db.snapshot();
+
+    // an entity replaced by another one with the same key is a change, for generated and composite keys alike
+    // (the alias is removed along with the tag, since its key contains the key of the tag)
+    tag.remove(db).unwrap();
+    TagDb::insert_with_id(db, tag, |id| Tag { id, name: "b".into() }).unwrap();
+    TagDb::insert_with_id(db, alias, |id| TagAlias { id, name: "b".into() }).unwrap();
+    assert!(matches!(db.delta::<Tag>(rev).unwrap().collect::<Vec<_>>()[..], [Delta::Update { .. }]));
+    assert!(matches!(db.delta::<TagAlias>(rev).unwrap().collect::<Vec<_>>()[..], [Delta::Update { .. }]));
+}
+
+#[test]
+fn rollback_timestamps() {
+    let mut db = AppDb::new();
+    let db = &mut db;
+    let tags = |db: &AppDb| -> TagDbStore { HasStore::<TagDbStore>::store(db).clone() };
+    let rev = db.snapshot();
+    TagDb::insert(db, |id| Tag { id, name: "a".into() }).unwrap();
+    let seen = tags(db).timestamp();
+    db.rollback(rev).unwrap();
+    assert_eq!(tags(db).changes_since(0).count(), 0);
+
+    // timestamps are not reused after a rollback: the new changes are after those already seen
+    TagDb::insert(db, |id| Tag { id, name: "b".into() }).unwrap();
+    assert!(tags(db).timestamp() > seen);
+    assert_eq!(tags(db).changes_since(seen).count(), 2);
+}
@@ -0,0 +1,64 @@
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+        year: u32,
+        rel album_artist: Artist? on delete nullify
+    }
+
+    Track(TrackId) {
+        name: String,
+        rel album: Album on delete cascade,
+        rel artist: Artist,
+        rel playlists: Playlist*.tracks
+    }
+
+    Playlist(PlaylistId) {
+        name: String,
+        rel tracks: Track*.playlists
+    }
+
+    Artist(ArtistId) {
+        name: String
+    }
+
+    Node(NodeId) {
+        rel parent: Node? on delete cascade
+    }
+}
+
+common::single_store_db!(TrackDbStore);
+
+#[test]
+fn delete_rules() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let artist = db.insert(|id| Artist { id, name: "ar".into() }).unwrap();
+    let album = db.insert(|id| Album { id, name: "a".into(), year: 2000, album_artist: Some(artist) }).unwrap();
+    let t1 = db.insert(|id| Track { id, name: "t1".into(), album, artist }).unwrap();
+    let t2 = db.insert(|id| Track { id, name: "t2".into(), album, artist }).unwrap();
+    let p = db.insert(|id| Playlist { id, name: "p".into() }).unwrap();
+    p.add_tracks(db, t1).unwrap();
+    p.add_tracks(db, t2).unwrap();
+    assert!(matches!(artist.remove(db), Err(kyuudb::Error::RelationshipDeniedDelete)));
+    album.remove(db).unwrap();
+    assert_eq!(Track::all(db).count(), 0);
+    assert_eq!(p.tracks(db).count(), 0);
+    let album = db.insert(|id| Album { id, name: "b".into(), year: 2000, album_artist: Some(artist) }).unwrap();
+    artist.remove(db).unwrap();
+    assert_eq!(album.album_artist(db), None);
+    assert!(matches!(artist.remove(db), Err(kyuudb::Error::EntityNotFound)));
+
+    let a = db.insert(|id| Node { id, parent: None }).unwrap();
+    let b = db.insert(|id| Node { id, parent: Some(a) }).unwrap();
+    let c = db.insert(|id| Node { id, parent: Some(b) }).unwrap();
+    assert!(matches!(a.set_parent(db, Some(c)), Err(kyuudb::Error::CycleDetected)));
+    b.remove(db).unwrap();
+    assert!(!db.s.Node.contains(c));
+    assert_eq!(Node::all(db).count(), 1);
+}
@@ -0,0 +1,48 @@
+use kyuudb::{Delta, Entity, EntityStore};
+use kyuudb_macros::store;
+
+store! {
+    pub store MusicDb;
+
+    Album(AlbumId) {
+        name: String,
+        rel tracks: Track*.album,
+    }
+
+    Track(TrackId) {
+        name: String,
+        rel album: Album.tracks on delete cascade,
+    }
+}
+
+/// Returns the ids of all entities of type `T`, in any store.
+fn ids<T: Entity>(store: &T::Store) -> Vec<T::Id> {
+    EntityStore::<T>::iter(store).map(Entity::id).collect()
+}
+
+#[test]
+fn entity_store() {
+    let mut store = MusicDbStore::new();
+    let a = EntityStore::<Album>::insert(&mut store, |id| Album { id, name: "a".into() }).unwrap();
+    let t1 = EntityStore::<Track>::insert(&mut store, |id| Track { id, name: "t1".into(), album: a }).unwrap();
+    let prev = store.clone();
+    let t2 = EntityStore::<Track>::insert(&mut store, |id| Track { id, name: "t2".into(), album: a }).unwrap();
+
+    assert_eq!(ids::<Track>(&store), vec![t1, t2]);
+    assert_eq!(EntityStore::<Track>::len(&store), 2);
+    assert!(EntityStore::<Track>::contains(&store, t1));
+    assert_eq!(EntityStore::<Track>::get(&store, t2).unwrap().name, "t2");
+
+    let delta: Vec<_> = EntityStore::<Track>::delta(&store, &prev).collect();
+    assert!(matches!(delta[..], [Delta::Insert(track)] if track.id == t2));
+
+    // delete rules are applied, in a single operation
+    let (ts, count) = (store.timestamp(), store.changes_since(0).count());
+    EntityStore::<Album>::remove(&mut store, a).unwrap();
+    assert_eq!(store.timestamp(), ts + 1);
+    let removed = store.changes_since(0).count() - count;
+    assert!(removed > 0);
+    assert_eq!(store.changes_since(ts).count(), removed);
+    assert!(EntityStore::<Track>::is_empty(&store));
+    assert!(EntityStore::<Track>::get(&store, t1).is_none());
+}
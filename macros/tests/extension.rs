@@ -0,0 +1,116 @@
+use kyuudb::Error;
+use kyuudb_macros::{database, store};
+
+/// The store extended by `ExtendedTrackDb`.
+mod base {
+    use kyuudb_macros::store;
+
+    store! {
+        pub store TrackDb;
+
+        Album(AlbumId) {
+            name: String,
+            rel tracks: Track*.album
+        }
+
+        Track(TrackId) {
+            name: String,
+            rel album: Album.tracks on delete cascade
+        }
+    }
+
+    pub fn add_album<DB: TrackDb>(db: &mut DB, name: &str) -> AlbumId {
+        db.insert(|id| Album { id, name: name.into() }).unwrap()
+    }
+
+    pub fn add_track<DB: TrackDb>(db: &mut DB, name: &str, album: AlbumId) -> TrackId {
+        db.insert(|id| Track { id, name: name.into(), album }).unwrap()
+    }
+}
+
+use base::{add_album, add_track, Album, Track, TrackDb};
+
+store! {
+    pub store ExtendedTrackDb : base::TrackDb;
+
+    AlbumExt[Album] {
+        rating: u32,
+        rel studio: RecordingStudio?.albums,
+        rel producers: Producer*.albums
+    }
+
+    TrackExt[Track] {
+        rel remix_of: Track?.remixes
+    }
+
+    RecordingStudio(RecordingStudioId) {
+        name: String,
+        rel albums: Album*.studio
+    }
+
+    Producer(ProducerId) {
+        name: String,
+        rel albums: Album*.producers,
+        rel favorite_tracks: Track* on delete deny
+    }
+}
+
+database! {
+    pub Db {
+        base::TrackDb,
+        ExtendedTrackDb : base::TrackDb,
+    }
+}
+
+#[test]
+fn extension() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let a = add_album(db, "a");
+    let b = add_album(db, "b");
+    let t1 = add_track(db, "t1", a);
+    let t2 = add_track(db, "t2", b);
+    let s = ExtendedTrackDb::insert(db, |id| RecordingStudio { id, name: "s".into() }).unwrap();
+    let p = ExtendedTrackDb::insert(db, |id| Producer { id, name: "p".into() }).unwrap();
+
+    // attributes and foreign keys
+    assert_eq!(a.rating(db), None);
+    a.set_rating(db, Some(5)).unwrap();
+    assert_eq!(a.rating(db), Some(&5));
+    a.set_studio(db, Some(s)).unwrap();
+    b.set_studio(db, Some(s)).unwrap();
+    assert_eq!(a.studio(db), Some(s));
+    assert_eq!(s.albums(db).collect::<Vec<_>>(), vec![a, b]);
+    t2.set_remix_of(db, Some(t1)).unwrap();
+    assert_eq!(t1.remixes(db).collect::<Vec<_>>(), vec![t2]);
+
+    // many-to-many relationships with entities of the base store
+    a.add_producers(db, p).unwrap();
+    p.add_albums(db, b).unwrap();
+    assert_eq!(p.albums(db).collect::<Vec<_>>(), vec![a, b]);
+    assert_eq!(b.producers(db).collect::<Vec<_>>(), vec![p]);
+    p.add_favorite_tracks(db, t2).unwrap();
+
+    // deny rule of the extension
+    assert!(matches!(b.remove(db), Err(Error::RelationshipDeniedDelete)));
+    assert!(TrackDb::remove::<Album>(db, b).is_err());
+    assert_eq!(b.studio(db), Some(s));
+    p.remove_favorite_tracks(db, t2).unwrap();
+
+    // removing from the base store removes the data of extensions
+    t1.remove(db).unwrap();
+    assert_eq!(t2.remix_of(db), None);
+    b.remove(db).unwrap();
+    assert_eq!(s.albums(db).collect::<Vec<_>>(), vec![a]);
+    assert_eq!(p.albums(db).collect::<Vec<_>>(), vec![a]);
+    assert!(matches!(b.set_rating(db, Some(1)), Err(Error::EntityNotFound)));
+    assert!(matches!(p.add_albums(db, b), Err(Error::ForeignKeyViolation)));
+    assert!(t2.remix_of(db).is_none());
+
+    // removing entities of the extension store nullifies the extensions
+    s.remove(db).unwrap();
+    assert_eq!(a.studio(db), None);
+    p.remove(db).unwrap();
+    assert_eq!(a.producers(db).count(), 0);
+    assert_eq!(a.rating(db), Some(&5));
+}
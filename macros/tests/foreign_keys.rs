@@ -0,0 +1,53 @@
+use kyuudb::Error;
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+        rel album_artist: Artist?
+    }
+
+    Track(TrackId) {
+        name: String,
+        rel album: Album on delete cascade
+    }
+
+    Artist(ArtistId) {
+        name: String
+    }
+
+    Playlist(PlaylistId) {
+        rel tracks: Track*
+    }
+}
+
+common::single_store_db!(TrackDbStore);
+
+#[test]
+fn foreign_keys() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let artist = db.insert(|id| Artist { id, name: "ar".into() }).unwrap();
+    let a = db.insert(|id| Album { id, name: "a".into(), album_artist: Some(artist) }).unwrap();
+    let b = db.insert(|id| Album { id, name: "b".into(), album_artist: None }).unwrap();
+    let t = db.insert(|id| Track { id, name: "t".into(), album: a }).unwrap();
+    b.remove(db).unwrap();
+    assert!(matches!(db.insert(|id| Track { id, name: "t".into(), album: b }), Err(Error::ForeignKeyViolation)));
+    assert!(matches!(t.set_album(db, b), Err(Error::ForeignKeyViolation)));
+    // setters of removed entities fail
+    assert!(matches!(b.set_name(db, "b".into()), Err(Error::EntityNotFound)));
+    assert!(matches!(b.set_album_artist(db, None), Err(Error::EntityNotFound)));
+    assert_eq!(t.album(db), a);
+    artist.remove(db).unwrap();
+    assert!(matches!(a.set_album_artist(db, Some(artist)), Err(Error::ForeignKeyViolation)));
+    assert!(matches!(db.insert(|id| Album { id, name: "c".into(), album_artist: Some(artist) }), Err(Error::ForeignKeyViolation)));
+    a.set_album_artist(db, None).unwrap();
+    let p = db.insert(|id| Playlist { id }).unwrap();
+    p.add_tracks(db, t).unwrap();
+    t.remove(db).unwrap();
+    assert!(matches!(p.add_tracks(db, t), Err(Error::ForeignKeyViolation)));
+}
@@ -0,0 +1,26 @@
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store ArtistDb;
+
+    /// An artist.
+    #[derive(Clone, Debug, PartialEq)]
+    #[id(derive(Debug, Default), doc = "Key of an artist.")]
+    Artist(u32, u32) {
+        name: String,
+    }
+}
+
+common::single_store_db!(ArtistDbStore);
+
+#[test]
+fn forward_attrs() {
+    let mut db = Db::default();
+    let id = NewArtist::new(ArtistId(1, 2), "a".into()).insert(&mut db).unwrap();
+    let row = db.s[id].clone();
+    assert_eq!(row, db.s[id]);
+    assert!(format!("{:?}", row).contains("\"a\""));
+    assert_eq!(ArtistId::default(), ArtistId(0, 0));
+}
@@ -0,0 +1,52 @@
+use kyuudb::Error;
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store NodeDb;
+
+    Node(NodeId) {
+        name: String,
+        rel parent: Node?.children on delete cascade,
+        rel children: Node*.parent,
+    }
+}
+
+common::single_store_db!(NodeDbStore);
+
+#[test]
+fn hierarchy() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let node = |db: &mut Db, name: &str, parent: Option<NodeId>| NewNode { parent, ..NewNode::new(name.into()) }.insert(db).unwrap();
+    let a = node(db, "a", None);
+    let b = node(db, "b", Some(a));
+    let c = node(db, "c", Some(b));
+    let d = node(db, "d", Some(a));
+    let e = node(db, "e", Some(b));
+
+    assert_eq!(c.ancestors(db).collect::<Vec<_>>(), [b, a]);
+    assert_eq!(c.root(db), a);
+    assert_eq!(a.root(db), a);
+    assert_eq!(c.depth(db), 2);
+    assert_eq!(a.depth(db), 0);
+    assert_eq!(a.descendants(db).collect::<Vec<_>>(), [b, c, e, d]);
+    assert_eq!(a.descendants_breadth_first(db).collect::<Vec<_>>(), [b, d, c, e]);
+    assert_eq!(a.children(db).collect::<Vec<_>>(), [b, d]);
+
+    assert!(matches!(a.set_parent(db, Some(c)), Err(Error::CycleDetected)));
+    assert!(matches!(a.set_parent(db, Some(a)), Err(Error::CycleDetected)));
+    assert_eq!(a.parent(db), None);
+    d.set_parent(db, Some(c)).unwrap();
+    assert_eq!(a.descendants(db).collect::<Vec<_>>(), [b, c, d, e]);
+    b.set_parent(db, None).unwrap();
+    a.set_parent(db, Some(e)).unwrap();
+    assert_eq!(a.root(db), b);
+
+    b.remove(db).unwrap();
+    assert_eq!(db.s.Node.len(), 0);
+    // removed entities have no ancestors, and can't be modified
+    assert_eq!(c.ancestors(db).count(), 0);
+    assert!(matches!(c.set_parent(db, None), Err(Error::EntityNotFound)));
+}
@@ -0,0 +1,81 @@
+use kyuudb::Error;
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store ShapeDb;
+
+    abstract Shape(ShapeId) {
+        name: String,
+        color: u32?,
+    }
+
+    Circle(CircleId) : Shape {
+        radius: f64,
+    }
+
+    Rect(RectId) : Shape {
+        width: f64,
+        height: f64,
+    }
+
+    Label(LabelId) {
+        text: String,
+        rel shape: Shape.labels on delete cascade,
+    }
+
+    Anchor(AnchorId) {
+        rel shape: Shape?.anchors on delete nullify,
+    }
+
+    Group(GroupId) {
+        rel shapes: Shape*,
+    }
+}
+
+common::single_store_db!(ShapeDbStore);
+
+#[test]
+fn inheritance() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let c = NewCircle::new("c".into(), 1.0).insert(db).unwrap();
+    let r = NewRect::new("r".into(), 2.0, 3.0).insert(db).unwrap();
+    assert_eq!(c.name(db), "c");
+    let sc = ShapeId::from(c);
+    let sr = ShapeId::from(r);
+    assert!(matches!(sr, ShapeId::Rect(id) if id == r));
+    assert_eq!(sc.name(db), "c");
+    sr.set_color(db, Some(7)).unwrap();
+    assert_eq!(r.color(db), Some(&7));
+    sr.clear_color(db).unwrap();
+    assert_eq!(r.color(db), None);
+    assert!(matches!(sr.get(db), Shape::Rect(row) if row.width == 2.0));
+
+    let all: Vec<_> = Shape::all(db).map(|s| s.id()).collect();
+    assert_eq!(all, [sc, sr]);
+
+    let l = NewLabel::new("l".into(), sc).insert(db).unwrap();
+    let a = NewAnchor { shape: Some(sr) }.insert(db).unwrap();
+    let g = NewGroup::new().insert(db).unwrap();
+    g.add_shapes(db, sc).unwrap();
+    g.add_shapes(db, sr).unwrap();
+    assert_eq!(sc.labels(db).collect::<Vec<_>>(), [l]);
+    assert_eq!(sr.anchors(db).collect::<Vec<_>>(), [a]);
+
+    // the foreign key must point to an existing entity of any subtype
+    let r2 = NewRect::new("r2".into(), 1.0, 1.0).insert(db).unwrap();
+    r2.remove(db).unwrap();
+    assert!(matches!(l.set_shape(db, ShapeId::Rect(r2)), Err(Error::ForeignKeyViolation)));
+    l.set_shape(db, sr).unwrap();
+    l.set_shape(db, sc).unwrap();
+
+    c.remove(db).unwrap();
+    assert!(!db.s.Label.contains(l));
+    assert_eq!(g.shapes(db).collect::<Vec<_>>(), [sr]);
+    sr.remove(db).unwrap();
+    assert_eq!(a.shape(db), None);
+    assert_eq!(g.shapes(db).count(), 0);
+    assert_eq!(Shape::all(db).count(), 0);
+}
@@ -0,0 +1,58 @@
+use kyuudb::Error;
+use kyuudb_macros::store;
+
+mod common;
+
+pub type Position = u32;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+        #[default = 1970]
+        year: u32,
+        #[default]
+        tags: Vec<String>,
+    }
+
+    Track(TrackId) {
+        name: String,
+        rel album: Album.tracks,
+        rel previous: Track?,
+    }
+
+    Playlist(PlaylistId) {
+        #[default = "untitled".to_string()]
+        name: String,
+    }
+
+    PlaylistEntry(PlaylistId, Position) {
+        rel track: Track,
+    }
+}
+
+common::single_store_db!(TrackDbStore);
+
+#[test]
+fn initializer() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let a = NewAlbum::new("a".into()).insert(db).unwrap();
+    assert_eq!(*a.year(db), 1970);
+    assert!(a.tags(db).is_empty());
+    let b = NewAlbum { year: 2000, ..NewAlbum::new("b".into()) }.insert(db).unwrap();
+    assert_eq!(*b.year(db), 2000);
+
+    let t = NewTrack::new("t".into(), a).insert(db).unwrap();
+    assert_eq!(t.album(db), a);
+    assert_eq!(t.previous(db), None);
+    b.remove(db).unwrap();
+    assert!(matches!(NewTrack::new("u".into(), b).insert(db), Err(Error::ForeignKeyViolation)));
+
+    let p = NewPlaylist::default().insert(db).unwrap();
+    assert_eq!(p.name(db), "untitled");
+    let e = NewPlaylistEntry::new(PlaylistEntryId(p, 0), t).insert(db).unwrap();
+    assert_eq!(e, PlaylistEntryId(p, 0));
+    assert!(matches!(NewPlaylistEntry::new(e, t).insert(db), Err(Error::DuplicateKey)));
+}
@@ -0,0 +1,56 @@
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+        rel cover: Image unique on delete cascade,
+        /// Tracks of the album.
+        rel tracks: Track*.album
+    }
+
+    Image(ImageId) {
+        path: String,
+        rel album: Album?.cover
+    }
+
+    Track(TrackId) {
+        name: String,
+        rel album: Album on delete cascade,
+        rel artist: Artist.tracks
+    }
+
+    Artist(ArtistId) {
+        name: String
+    }
+
+    Playlist(PlaylistId) {
+        rel tracks: Track*.playlists
+    }
+}
+
+common::single_store_db!(TrackDbStore);
+
+#[test]
+fn inverse() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let i1 = db.insert(|id| Image { id, path: "1".into() }).unwrap();
+    let ar = db.insert(|id| Artist { id, name: "1".into() }).unwrap();
+    let a = db.insert(|id| Album { id, name: "a".into(), cover: i1 }).unwrap();
+    let i2 = db.insert(|id| Image { id, path: "2".into() }).unwrap();
+    let b = db.insert(|id| Album { id, name: "a".into(), cover: i2 }).unwrap();
+    let t1 = db.insert(|id| Track { id, name: "t1".into(), album: a, artist: ar }).unwrap();
+    let t2 = db.insert(|id| Track { id, name: "t2".into(), album: b, artist: ar }).unwrap();
+    let t3 = db.insert(|id| Track { id, name: "t3".into(), album: a, artist: ar }).unwrap();
+    assert_eq!(a.tracks(db).collect::<Vec<_>>(), vec![t1, t3]);
+    assert_eq!(b.tracks(db).collect::<Vec<_>>(), vec![t2]);
+    assert_eq!(ar.tracks(db).count(), 3);
+    assert_eq!(i1.album(db), Some(a));
+    let p = db.insert(|id| Playlist { id }).unwrap();
+    p.add_tracks(db, t2).unwrap();
+    assert_eq!(t2.playlists(db).collect::<Vec<_>>(), vec![p]);
+}
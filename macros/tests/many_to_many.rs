@@ -0,0 +1,57 @@
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+        year: u32
+    }
+
+    Track(TrackId) {
+        name: String,
+        rel album: Album,
+        rel playlists: Playlist*.tracks
+    }
+
+    Playlist(PlaylistId) {
+        name: String,
+        rel tracks: Track*.playlists
+    }
+
+    Person(PersonId) {
+        rel friends: Person*
+    }
+}
+
+common::single_store_db!(TrackDbStore);
+
+#[test]
+fn many_to_many() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let album = db.insert(|id| Album { id, name: "a".into(), year: 2000 }).unwrap();
+    let t1 = db.insert(|id| Track { id, name: "t1".into(), album }).unwrap();
+    let t2 = db.insert(|id| Track { id, name: "t2".into(), album }).unwrap();
+    let p = db.insert(|id| Playlist { id, name: "p".into() }).unwrap();
+    p.add_tracks(db, t1).unwrap();
+    p.add_tracks(db, t2).unwrap();
+    t2.add_playlists(db, p).unwrap();
+    assert_eq!(p.tracks(db).collect::<Vec<_>>(), vec![t1, t2]);
+    assert_eq!(t1.playlists(db).collect::<Vec<_>>(), vec![p]);
+    db.remove::<Track>(t1).unwrap();
+    assert_eq!(p.tracks(db).collect::<Vec<_>>(), vec![t2]);
+    p.remove_tracks(db, t2).unwrap();
+    assert_eq!(t2.playlists(db).count(), 0);
+    t2.add_playlists(db, p).unwrap();
+    db.remove::<Playlist>(p).unwrap();
+    assert_eq!(t2.playlists(db).count(), 0);
+    let a = db.insert(|id| Person { id }).unwrap();
+    let b = db.insert(|id| Person { id }).unwrap();
+    a.add_friends(db, b).unwrap();
+    b.add_friends(db, a).unwrap();
+    db.remove::<Person>(a).unwrap();
+    assert_eq!(b.friends(db).count(), 0);
+}
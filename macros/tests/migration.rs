@@ -0,0 +1,153 @@
+use kyuudb::migration::{Migrations, StoreData, Value};
+use kyuudb::{Error, HasStore};
+use kyuudb_macros::store;
+
+mod common;
+
+/// Version 1 of the schema: artists are stored as a name in the albums.
+#[allow(dead_code)]
+mod v1 {
+    use kyuudb_macros::store;
+
+    store! {
+        #[version(1)]
+        pub store MusicDb;
+
+        Album(AlbumId) {
+            title: String,
+            artist_name: String,
+            rel tracks: Track[],
+        }
+
+        Track(TrackId) {
+            name: String,
+        }
+    }
+
+    crate::common::single_store_db!(MusicDbStore);
+}
+
+store! {
+    #[version(2)]
+    pub store MusicDb;
+
+    Artist(ArtistId) {
+        #[unique] name: String,
+        rel albums: Album*.artist,
+    }
+
+    Album(AlbumId) {
+        #[index] name: String,
+        year: u32?,
+        rel artist: Artist.albums,
+        rel tracks: Track[],
+    }
+
+    Track(TrackId) {
+        name: String,
+        rel genres: Genre*,
+    }
+
+    Genre(GenreId) {
+        name: String,
+    }
+}
+
+common::single_store_db!(MusicDbStore);
+
+/// Upgrades version 1: renames `Album.title`, and moves artist names to a new `Artist` entity.
+fn migrations() -> Migrations {
+    let mut migrations = Migrations::new();
+    migrations.add(1, |data: &mut StoreData| {
+        data.rename_attribute("Album", "title", "name");
+        let mut artists: Vec<String> = vec![];
+        for album in data.rows_mut("Album") {
+            let Some(Value::String(name)) = album.remove("artist_name") else {
+                return Err(Error::InvalidValue { entity: "Album", attribute: "artist_name" });
+            };
+            let index = artists.iter().position(|artist| *artist == name).unwrap_or_else(|| {
+                artists.push(name);
+                artists.len() - 1
+            });
+            album.insert("artist".into(), Value::Id(index as u32));
+        }
+        for (index, name) in artists.into_iter().enumerate() {
+            let row = [("id".to_string(), Value::Id(index as u32)), ("name".to_string(), Value::String(name))];
+            data.rows_mut("Artist").push(row.into_iter().collect());
+        }
+        Ok(())
+    });
+    migrations
+}
+
+#[test]
+fn migration() {
+    let old = &mut v1::Db::default();
+    let t1 = v1::NewTrack::new("t1".into()).insert(old).unwrap();
+    let t2 = v1::NewTrack::new("t2".into()).insert(old).unwrap();
+    let a1 = v1::NewAlbum::new("a1".into(), "x".into()).insert(old).unwrap();
+    v1::NewAlbum::new("a2".into(), "x".into()).insert(old).unwrap();
+    v1::NewAlbum::new("a3".into(), "y".into()).insert(old).unwrap();
+    a1.insert_tracks_at(old, 0, t2).unwrap();
+    a1.insert_tracks_at(old, 1, t1).unwrap();
+    a1.insert_tracks_at(old, 2, t2).unwrap();
+    let data = old.store().export();
+    assert_eq!(data.version, 1);
+
+    assert!(matches!(MusicDbStore::import(data.clone(), &Migrations::new()), Err(Error::MissingMigration { version: 1 })));
+
+    let db = &mut Db { s: MusicDbStore::import(data, &migrations()).unwrap() };
+    assert_eq!(MusicDbStore::VERSION, 2);
+    assert_eq!(MusicDbStore::SCHEMA.version, Some(2));
+
+    // indices are rebuilt
+    let x = Artist::get_by_name(db, "x").unwrap();
+    let albums: Vec<_> = x.albums(db).map(|a| a.name(db).clone()).collect();
+    assert_eq!(albums, ["a1", "a2"]);
+    let a1 = Album::by_name(db, "a1").next().unwrap();
+    assert!(a1.year(db).is_none());
+    let tracks: Vec<_> = a1.tracks(db).map(|t| t.name(db).clone()).collect();
+    assert_eq!(tracks, ["t2", "t1", "t2"]);
+
+    // round trip
+    let g = NewGenre::new("g".into()).insert(db).unwrap();
+    let t = Track::all(db).next().unwrap().id;
+    t.add_genres(db, g).unwrap();
+    let copy = MusicDbStore::import(db.s.export(), &Migrations::new()).unwrap();
+    assert_eq!(copy.export(), db.s.export());
+
+    // integrity is checked
+    let mut data = db.s.export();
+    data.map_values("Album", "artist", |_| Value::Id(100));
+    assert!(matches!(MusicDbStore::import(data, &Migrations::new()), Err(Error::ForeignKeyViolation)));
+    let mut data = db.s.export();
+    data.map_values("Artist", "name", |_| Value::String("z".into()));
+    assert!(matches!(MusicDbStore::import(data, &Migrations::new()), Err(Error::UniqueViolation { .. })));
+    let mut data = db.s.export();
+    data.remove_attribute("Album", "name");
+    assert!(matches!(
+        MusicDbStore::import(data, &Migrations::new()),
+        Err(Error::InvalidValue { entity: "Album", attribute: "name" })
+    ));
+    let mut data = db.s.export();
+    data.rows_mut("Album")[0].insert("rating".into(), Value::UInt(5));
+    assert!(matches!(
+        MusicDbStore::import(data, &Migrations::new()),
+        Err(Error::UnknownAttribute { entity: "Album", ref attribute }) if attribute == "rating"
+    ));
+    let mut data = db.s.export();
+    data.rename_entity("Genre", "Style");
+    assert!(matches!(
+        MusicDbStore::import(data, &Migrations::new()),
+        Err(Error::UnknownEntity { ref entity }) if entity == "Style"
+    ));
+    let mut data = db.s.export();
+    data.map_values("Album", "artist", |_| Value::Id(u32::MAX));
+    assert!(matches!(
+        MusicDbStore::import(data, &Migrations::new()),
+        Err(Error::InvalidValue { entity: "Album", attribute: "artist" })
+    ));
+    let mut data = db.s.export();
+    data.version = 3;
+    assert!(matches!(MusicDbStore::import(data, &Migrations::new()), Err(Error::UnsupportedVersion { version: 3 })));
+}
@@ -0,0 +1,40 @@
+use kyuudb::{Entity, Error};
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store AlbumDb;
+
+    #[cluster(year, id)]
+    Album(AlbumId) {
+        name: String,
+        #[check(|c| !c.is_empty())]
+        comment: String?,
+        year: u32?,
+    }
+}
+
+common::single_store_db!(AlbumDbStore);
+
+#[test]
+fn optional_attr() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let a = NewAlbum::new("a".into()).insert(db).unwrap();
+    assert_eq!(a.comment(db), None);
+    a.set_comment(db, Some("nice".into())).unwrap();
+    assert_eq!(a.comment(db).map(String::as_str), Some("nice"));
+    assert!(matches!(a.set_comment(db, Some("".into())), Err(Error::CheckViolation { constraint: "Album.comment" })));
+    a.clear_comment(db).unwrap();
+    assert_eq!(a.comment(db), None);
+
+    let b = NewAlbum { year: Some(1990), ..NewAlbum::new("b".into()) }.insert(db).unwrap();
+    let c = NewAlbum { year: Some(1980), ..NewAlbum::new("c".into()) }.insert(db).unwrap();
+    let order = |db: &Db| Album::all(db).map(|a| a.id()).collect::<Vec<_>>();
+    assert_eq!(order(db), [a, c, b]);
+    c.clear_year(db).unwrap();
+    assert_eq!(order(db), [a, c, b]);
+    a.set_year(db, Some(2000)).unwrap();
+    assert_eq!(order(db), [c, b, a]);
+}
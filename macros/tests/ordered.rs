@@ -0,0 +1,70 @@
+use kyuudb::{Error, ListDelta};
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store PlaylistDb;
+
+    Track(TrackId) {
+        name: String,
+        rel playlists: Playlist*.tracks,
+    }
+
+    Playlist(PlaylistId) {
+        name: String,
+        rel tracks: Track[].playlists,
+    }
+}
+
+common::single_store_db!(PlaylistDbStore);
+
+#[test]
+fn ordered() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let a = NewTrack::new("a".into()).insert(db).unwrap();
+    let b = NewTrack::new("b".into()).insert(db).unwrap();
+    let c = NewTrack::new("c".into()).insert(db).unwrap();
+    let p = NewPlaylist::new("p".into()).insert(db).unwrap();
+    let q = NewPlaylist::new("q".into()).insert(db).unwrap();
+    let tracks = |db: &Db| p.tracks(db).collect::<Vec<_>>();
+
+    p.insert_tracks_at(db, 0, a).unwrap();
+    p.insert_tracks_at(db, 1, b).unwrap();
+    p.insert_tracks_at(db, 0, c).unwrap();
+    p.insert_tracks_at(db, 2, a).unwrap();
+    assert_eq!(tracks(db), [c, a, a, b]);
+    assert!(matches!(p.insert_tracks_at(db, 5, a), Err(Error::PositionOutOfBounds { index: 5, len: 4 })));
+    q.insert_tracks_at(db, 0, a).unwrap();
+    assert_eq!(a.playlists(db).collect::<Vec<_>>(), [p, q]);
+    assert_eq!(b.playlists(db).collect::<Vec<_>>(), [p]);
+
+    // many insertions at the same place
+    for _ in 0..100 {
+        q.insert_tracks_at(db, 1, b).unwrap();
+        q.insert_tracks_at(db, 1, c).unwrap();
+    }
+    assert_eq!(q.tracks(db).count(), 201);
+    assert_eq!(q.tracks(db).take(3).collect::<Vec<_>>(), [a, c, b]);
+    assert_eq!(q.tracks(db).last(), Some(b));
+
+    let prev = db.clone();
+    p.move_tracks(db, 3, 0).unwrap();
+    assert_eq!(tracks(db), [b, c, a, a]);
+    assert_eq!(
+        PlaylistId::tracks_delta(db, &prev).collect::<Vec<_>>(),
+        [ListDelta::Move { source: p, target: b, from: 3, to: 0 }]
+    );
+    assert_eq!(p.remove_tracks_at(db, 1).unwrap(), c);
+    assert_eq!(tracks(db), [b, a, a]);
+
+    a.remove(db).unwrap();
+    assert_eq!(tracks(db), [b]);
+    assert_eq!(q.tracks(db).count(), 200);
+    let delta: Vec<_> = PlaylistId::tracks_delta(db, &prev).filter(|d| matches!(d, ListDelta::Remove { source, .. } if *source == p)).collect();
+    assert_eq!(delta.len(), 3);
+    q.remove(db).unwrap();
+    assert_eq!(b.playlists(db).collect::<Vec<_>>(), [p]);
+    assert!(matches!(p.move_tracks(db, 0, 1), Err(Error::PositionOutOfBounds { .. })));
+}
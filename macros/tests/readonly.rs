@@ -0,0 +1,39 @@
+use kyuudb_macros::store;
+
+mod common;
+
+#[allow(dead_code)]
+mod inner {
+    use super::*;
+
+    store! {
+        pub store FileDb;
+
+        File(FileId) {
+            #[readonly] hash: u64,
+            #[set(pub(crate))] name: String,
+            pub(self) size: u64,
+            #[readonly] comment: String?,
+        }
+    }
+
+    crate::common::single_store_db!(FileDbStore);
+
+    pub fn size(db: &Db, id: FileId) -> u64 {
+        *id.size(db)
+    }
+}
+
+use inner::*;
+
+#[test]
+fn readonly() {
+    let mut db = Db::default();
+    let id = NewFile { comment: Some("c".into()), ..NewFile::new(42, "a".into(), 3) }.insert(&mut db).unwrap();
+    assert_eq!(*id.hash(&db), 42);
+    id.set_name(&mut db, "b".into()).unwrap();
+    assert_eq!(id.name(&db), "b");
+    assert_eq!(size(&db, id), 3);
+    assert_eq!(id.comment(&db).unwrap(), "c");
+    assert!(FileDbStore::SCHEMA.entity("File").unwrap().attribute("hash").unwrap().readonly);
+}
@@ -0,0 +1,80 @@
+use kyuudb::schema::{DeleteRule, Multiplicity};
+use kyuudb::{Store, TableIndex};
+use kyuudb_macros::store;
+
+store! {
+    pub store MusicDb;
+
+    Album(AlbumId) {
+        #[unique] name: String,
+        comment: String?,
+        rel tracks: Track*.album,
+    }
+
+    Track(TrackId) {
+        #[index] name: String,
+        tags: Vec<String>,
+        label: Option<&'static str>,
+        credits: std::collections::BTreeMap<u32, String>,
+        fingerprint: [u8; 4],
+        gain: std::marker::PhantomData<dyn Fn(f64) -> f64 + Send + Sync>,
+        rel album: Album.tracks on delete cascade,
+    }
+
+    abstract Shape(ShapeId) {
+        name: String,
+    }
+
+    Circle(CircleId) : Shape {
+        radius: f64,
+    }
+}
+
+fn schema<S: Store>() -> &'static kyuudb::StoreSchema {
+    S::SCHEMA
+}
+
+#[test]
+fn schema_reflection() {
+    let schema = schema::<MusicDbStore>();
+    assert_eq!(schema.name, "MusicDb");
+    assert!(schema.base.is_none());
+    let names: Vec<_> = schema.entities.iter().map(|e| e.name).collect();
+    assert_eq!(names, ["Album", "Track", "Shape", "Circle"]);
+
+    let track = schema.entity("Track").unwrap();
+    assert_eq!(track.table, Some(TableIndex::from_u32(1)));
+    assert_eq!(schema.table(TableIndex::from_u32(1)).unwrap().name, "Track");
+    assert_eq!(track.key, ["TrackId"]);
+    let tags = track.attribute("tags").unwrap();
+    assert_eq!(tags.type_name, "Vec<String>");
+    let type_name = |name| track.attribute(name).unwrap().type_name;
+    assert_eq!(type_name("label"), "Option<&'static str>");
+    assert_eq!(type_name("credits"), "std::collections::BTreeMap<u32, String>");
+    assert_eq!(type_name("fingerprint"), "[u8; 4]");
+    assert_eq!(type_name("gain"), "std::marker::PhantomData<dyn Fn(f64) -> f64 + Send + Sync>");
+    assert!(track.attribute("name").unwrap().indexed);
+    let album = track.relation("album").unwrap();
+    assert_eq!(album.destination, "Album");
+    assert_eq!(album.multiplicity, Multiplicity::One);
+    assert_eq!(album.inverse, Some("tracks"));
+    assert_eq!(album.delete_rule, DeleteRule::Cascade);
+    assert!(album.stored);
+
+    let album = MusicDbStore::SCHEMA.entity("Album").unwrap();
+    assert!(album.attribute("name").unwrap().unique);
+    assert!(album.attribute("comment").unwrap().optional);
+    let tracks = album.relation("tracks").unwrap();
+    assert_eq!(tracks.multiplicity, Multiplicity::Many);
+    assert!(!tracks.stored);
+
+    let shape = schema.entity("Shape").unwrap();
+    assert!(shape.is_abstract);
+    assert_eq!(shape.table, None);
+    let circle = schema.entity("Circle").unwrap();
+    // abstract entities have no table, and are not numbered
+    assert_eq!(circle.table, Some(TableIndex::from_u32(2)));
+    assert_eq!(schema.table(TableIndex::from_u32(3)).map(|e| e.name), None);
+    assert_eq!(circle.supertype, Some("Shape"));
+    assert!(circle.attribute("name").is_some());
+}
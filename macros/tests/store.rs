@@ -676,6 +676,8 @@ fn join_test(db: &DbStore4, t: u64) {
 }
 
 
+// Sketch of incremental queries, commented out: `DB`, `TrackDB` and `album_0` are not defined yet.
+/*
 trait Query<DB> {
     type Key;
     type Output;    // usually Box<dyn Widget>
@@ -704,6 +706,7 @@ impl<K, V> NodeList<K,V> {
         // ...
     }
 }
+*/
 
 
 /*
@@ -0,0 +1,22 @@
+use kyuudb_macros::store;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+        rel tracks: Track*.name,
+        rel artist: Artist?.albums,
+    }
+
+    Track(TrackId) {
+        name: String,
+        rel album: Album,
+    }
+
+    Artist(ArtistId) {
+        rel albums: Track*.album,
+    }
+}
+
+fn main() {}
@@ -0,0 +1,21 @@
+use kyuudb_macros::store;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+    }
+
+    Track(TrackId) {
+        name: String,
+        rel album: Album.tracks,
+        rel bonus_album: Album?.tracks,
+    }
+
+    Single(SingleId) {
+        rel album: Album?.tracks,
+    }
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use kyuudb_macros::store;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+        name: String,
+        id: u32,
+    }
+
+    Album(OtherAlbumId) {
+        title: String,
+    }
+}
+
+fn main() {}
@@ -0,0 +1,25 @@
+use kyuudb_macros::store;
+
+mod base {
+    use kyuudb_macros::store;
+
+    store! {
+        pub store TrackDb;
+
+        Album(AlbumId) {
+            name: String,
+        }
+    }
+}
+
+store! {
+    pub store ExtendedTrackDb : base::TrackDb;
+
+    /// Ratings of albums.
+    #[derive(Debug)]
+    AlbumExt[Album] {
+        rating: u32,
+    }
+}
+
+fn main() {}
@@ -0,0 +1,19 @@
+use kyuudb_macros::store;
+
+store! {
+    pub store ShapeDb;
+
+    abstract Shape(ShapeId) {
+        name: String,
+    }
+
+    Circle(CircleId) : Shape {
+        radius: f64,
+    }
+
+    Label(ShapeId, u32) {
+        text: String,
+    }
+}
+
+fn main() {}
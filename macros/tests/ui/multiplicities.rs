@@ -0,0 +1,20 @@
+use kyuudb_macros::store;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        rel cover: Image?.album,
+        rel tracks: Track[].album,
+    }
+
+    Image(ImageId) {
+        rel album: Album?.cover,
+    }
+
+    Track(TrackId) {
+        rel album: Album[].tracks,
+    }
+}
+
+fn main() {}
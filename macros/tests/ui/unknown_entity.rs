@@ -0,0 +1,12 @@
+use kyuudb_macros::store;
+
+store! {
+    pub store TrackDb;
+
+    Track(TrackId) {
+        name: String,
+        rel album: Albun,
+    }
+}
+
+fn main() {}
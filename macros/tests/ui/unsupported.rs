@@ -0,0 +1,20 @@
+use kyuudb_macros::store;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        rel tracks: Track* unique,
+        rel cover: Image on delete nullify,
+    }
+
+    Track(TrackId) {
+        name: String,
+    }
+
+    Image(ImageId) {
+        path: String,
+    }
+}
+
+fn main() {}
@@ -0,0 +1,48 @@
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store TrackDb;
+
+    Album(AlbumId) {
+        name: String,
+        rel cover: Image unique on delete cascade
+    }
+
+    Image(ImageId) {
+        path: String
+    }
+
+    Port(PortId) {
+        rel connected_to: Port? unique
+    }
+}
+
+common::single_store_db!(TrackDbStore);
+
+#[test]
+fn unique() {
+    use kyuudb::Error;
+    let mut db = Db::default();
+    let db = &mut db;
+    let i1 = db.insert(|id| Image { id, path: "1".into() }).unwrap();
+    let i2 = db.insert(|id| Image { id, path: "2".into() }).unwrap();
+    let a = db.insert(|id| Album { id, name: "a".into(), cover: i1 }).unwrap();
+    assert!(matches!(db.insert(|id| Album { id, name: "b".into(), cover: i1 }), Err(Error::RelationshipTooManyTargets)));
+    let b = db.insert(|id| Album { id, name: "b".into(), cover: i2 }).unwrap();
+    assert!(matches!(b.set_cover(db, i1), Err(Error::RelationshipTooManyTargets)));
+    a.set_cover(db, i1).unwrap();
+    i2.remove(db).unwrap();
+    assert_eq!(Album::all(db).count(), 1);
+
+    let p1 = db.insert(|id| Port { id, connected_to: None }).unwrap();
+    let p2 = db.insert(|id| Port { id, connected_to: Some(p1) }).unwrap();
+    let p3 = db.insert(|id| Port { id, connected_to: None }).unwrap();
+    assert!(matches!(p3.set_connected_to(db, Some(p1)), Err(Error::RelationshipTooManyTargets)));
+    p2.set_connected_to(db, None).unwrap();
+    p3.set_connected_to(db, Some(p1)).unwrap();
+    p1.remove(db).unwrap();
+    assert_eq!(p3.connected_to(db), None);
+    p2.set_connected_to(db, Some(p3)).unwrap();
+}
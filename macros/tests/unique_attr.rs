@@ -0,0 +1,42 @@
+use kyuudb::Error;
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store UserDb;
+
+    User(UserId) {
+        #[unique]
+        email: String,
+        name: String,
+    }
+}
+
+common::single_store_db!(UserDbStore);
+
+#[test]
+fn unique_attr() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let a = db.insert(|id| User { id, email: "a@x".into(), name: "a".into() }).unwrap();
+    let b = db.insert(|id| User { id, email: "b@x".into(), name: "b".into() }).unwrap();
+    assert!(matches!(
+        db.insert(|id| User { id, email: "a@x".into(), name: "c".into() }),
+        Err(Error::UniqueViolation { entity: "User", attribute: "email" })
+    ));
+    assert_eq!(User::get_by_email(db, "a@x"), Some(a));
+    let (ts, count) = (db.s.timestamp(), db.s.changes_since(0).count());
+    assert!(matches!(b.set_email(db, "a@x".into()), Err(Error::UniqueViolation { .. })));
+    assert_eq!(*b.email(db), "b@x");
+    // a failed modification is not logged
+    assert_eq!((db.s.timestamp(), db.s.changes_since(0).count()), (ts, count));
+    a.set_email(db, "a@x".into()).unwrap();
+    a.set_email(db, "c@x".into()).unwrap();
+    assert_eq!(User::get_by_email(db, "a@x"), None);
+    b.set_email(db, "a@x".into()).unwrap();
+    assert_eq!(User::get_by_email(db, "a@x"), Some(b));
+    b.remove(db).unwrap();
+    assert_eq!(User::get_by_email(db, "a@x"), None);
+    db.insert(|id| User { id, email: "a@x".into(), name: "d".into() }).unwrap();
+}
@@ -0,0 +1,99 @@
+use kyuudb::Error;
+use kyuudb_macros::store;
+
+mod common;
+
+store! {
+    pub store MusicDb;
+
+    Album(AlbumId) {
+        #[unique] name: String,
+        rel tracks: Track*.album,
+    }
+
+    #[cluster(album, id)]
+    #[check(valid_year, |t| t.year >= 1900)]
+    Track(TrackId) {
+        #[index] title: String,
+        year: u32,
+        comment: String?,
+        #[readonly] hash: u64,
+        cover: Blob,
+        rel album: Album.tracks,
+    }
+
+    Node(NodeId) {
+        name: String,
+        rel parent: Node?.children,
+        rel children: Node*.parent,
+    }
+}
+
+/// An attribute that can't be compared.
+#[derive(Clone, Default)]
+pub struct Blob(Vec<u8>);
+
+common::single_store_db!(MusicDbStore);
+
+#[test]
+fn update() {
+    let mut db = Db::default();
+    let db = &mut db;
+    let a1 = NewAlbum::new("a1".into()).insert(db).unwrap();
+    let a2 = NewAlbum::new("a2".into()).insert(db).unwrap();
+    let t1 = NewTrack::new("t1".into(), 2000, 1, Blob::default(), a1).insert(db).unwrap();
+    let t2 = NewTrack::new("t2".into(), 2001, 2, Blob::default(), a1).insert(db).unwrap();
+
+    let t = db.s.timestamp();
+    Track::update(db, t1, |row| {
+        row.title = "t1'".into();
+        row.comment = Some("c".into());
+        row.album = a2;
+        row.cover = Blob(vec![1]);
+    })
+    .unwrap();
+    assert_eq!(db.s.timestamp(), t + 1);
+    // removed and inserted title and album, and updated the attributes without an index (comment and cover) as a
+    // whole
+    let changes: Vec<_> = db.s.changes_since(t).collect();
+    assert_eq!(changes.len(), 5);
+    assert!(changes.iter().any(|change| matches!(change, MusicDbChangeKind::Track_Updated(id) if *id == t1)));
+    assert_eq!(t1.cover(db).0, [1]);
+    assert_eq!(t1.title(db), "t1'");
+    assert_eq!(Track::by_title(db, "t1'").collect::<Vec<_>>(), vec![t1]);
+    assert_eq!(Track::by_title(db, "t1").count(), 0);
+    assert_eq!(a2.tracks(db).collect::<Vec<_>>(), vec![t1]);
+    assert_eq!(a1.tracks(db).collect::<Vec<_>>(), vec![t2]);
+    // clustered by album
+    assert_eq!(Track::all(db).map(|t| t.id).collect::<Vec<_>>(), vec![t2, t1]);
+
+    // failing updates leave the entity unchanged
+    let t = db.s.timestamp();
+    assert!(matches!(
+        Track::update(db, t1, |row| { row.title = "x".into(); row.year = 1800; }),
+        Err(Error::CheckViolation { constraint: "Track.valid_year" })
+    ));
+    assert!(matches!(
+        Album::update(db, a2, |row| row.name = "a1".into()),
+        Err(Error::UniqueViolation { .. })
+    ));
+    // an album that only exists in another database
+    let dangling = NewAlbum::new("a3".into()).insert(&mut db.clone()).unwrap();
+    assert!(matches!(Track::update(db, t1, |row| row.album = dangling), Err(Error::ForeignKeyViolation)));
+    assert_eq!(t1.title(db), "t1'");
+    assert_eq!(db.s.timestamp(), t);
+    Album::update(db, a2, |row| row.name = "a2".into()).unwrap();
+
+    // read-only attributes keep their value
+    Track::update(db, t1, |row| { row.hash = 3; row.year = 2002; }).unwrap();
+    assert_eq!(*t1.hash(db), 1);
+    assert_eq!(*t1.year(db), 2002);
+
+    // cycles
+    let n1 = NewNode::new("n1".into()).insert(db).unwrap();
+    let n2 = NewNode { parent: Some(n1), ..NewNode::new("n2".into()) }.insert(db).unwrap();
+    assert!(matches!(Node::update(db, n1, |row| row.parent = Some(n2)), Err(Error::CycleDetected)));
+    Node::update(db, n2, |row| row.parent = None).unwrap();
+    Node::update(db, n1, |row| row.parent = Some(n2)).unwrap();
+    assert_eq!(n2.children(db).collect::<Vec<_>>(), vec![n1]);
+}
@@ -0,0 +1,84 @@
+use im::Vector;
+use std::cmp::Ordering;
+
+#[derive(Clone, Debug)]
+struct Change<C> {
+    timestamp: u64,
+    kind: C,
+}
+
+/// Log of the changes made to the entities of a store, in the order they were made.
+///
+/// All changes made by one operation on the store (e.g. the removal of an entity, along with the entities removed by
+/// `cascade` rules and the nullified references to it) share the same timestamp.
+#[derive(Clone, Debug)]
+pub struct ChangeLog<C: Clone> {
+    timestamp: u64,
+    changes: Vector<Change<C>>,
+}
+
+impl<C: Clone> ChangeLog<C> {
+    pub fn new() -> ChangeLog<C> {
+        ChangeLog {
+            timestamp: 0,
+            changes: Vector::new(),
+        }
+    }
+
+    /// Returns the timestamp of the last operation, including operations discarded by `restore`.
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// Replaces the changes with those of a previous version of the log (e.g. when a database is rolled back).
+    ///
+    /// The timestamp is not rewound: the next operations get timestamps greater than those of the discarded
+    /// operations, which may already have been seen by `since`.
+    pub fn restore(&mut self, prev: &ChangeLog<C>) {
+        self.changes = prev.changes.clone();
+        self.timestamp = self.timestamp.max(prev.timestamp);
+    }
+
+    /// Starts a new operation: the changes pushed after this get a new timestamp, which is returned.
+    pub fn begin_operation(&mut self) -> u64 {
+        self.timestamp += 1;
+        self.timestamp
+    }
+
+    /// Records a change made by the current operation.
+    pub fn push(&mut self, kind: C) {
+        self.changes.push_back(Change {
+            timestamp: self.timestamp,
+            kind,
+        });
+    }
+
+    /// Returns the changes made by the operations after the one with the given timestamp.
+    pub fn since(&self, timestamp: u64) -> impl Iterator<Item = &C> + '_ {
+        let start = self
+            .changes
+            .binary_search_by(|change| {
+                if change.timestamp <= timestamp {
+                    Ordering::Less
+                } else {
+                    Ordering::Greater
+                }
+            })
+            .unwrap_or_else(|i| i);
+        self.changes.iter().skip(start).map(|change| &change.kind)
+    }
+
+    pub fn len(&self) -> usize {
+        self.changes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl<C: Clone> Default for ChangeLog<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
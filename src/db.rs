@@ -1,5 +1,5 @@
 use crate::{Index, Table};
-use crate::{Delta, Error};
+use crate::{Delta, Error, StoreSchema};
 use std::marker::PhantomData;
 use std::{fmt, mem, ops};
 use std::collections::Bound;
@@ -20,6 +20,10 @@ impl RevIndex {
     pub const fn new(revision: u32) -> Self {
         Self(revision)
     }
+
+    pub const fn revision(self) -> u32 {
+        self.0
+    }
 }
 
 /*pub trait Query<'a, DB: ?Sized> {
@@ -280,33 +284,139 @@ impl<K, V> RelIndex<K, V> where K: Ord, V: Ord {
     }
 }*/
 
-/// Entity index.
-pub trait EntityId: Copy + Eq + fmt::Debug + 'static {
+/// Primary key of an entity.
+///
+/// Keys are ordered: entities are stored in key order, and composite keys (e.g. `(PlaylistId, Position)`) can be
+/// looked up by prefix.
+pub trait EntityKey: Copy + Eq + Ord + fmt::Debug + 'static {
+    type Entity: Entity<Id = Self>;
+}
+
+/// Entity index, generated by the store when inserting entities.
+pub trait EntityId: EntityKey {
     fn from_u32(id: u32) -> Self;
     fn to_u32(self) -> u32;
 }
 
 /// Represents an entity.
 ///
-/// Usually it's implemented as a newtype for a `u32` index.
+/// Usually its key is a newtype for a `u32` index.
 pub trait Entity: 'static + Clone {
-    type Id: EntityId;
+    type Id: EntityKey<Entity = Self>;
+    /// The store that holds the entities of this type.
+    type Store: EntityStore<Self>;
     fn id(&self) -> Self::Id;
 }
 
-/*
+/// Bounds of a range of `(value, id)` entries of an index.
+pub type IndexRange<T, K> = (Bound<(T, K)>, Bound<(T, K)>);
+
+/// Converts a range of values into the range of `(value, id)` entries of an index, where `min` and `max` are the
+/// smallest and largest ids.
+pub fn index_range<T: Clone, K: Copy>(range: impl RangeBounds<T>, min: K, max: K) -> IndexRange<T, K> {
+    let start = match range.start_bound() {
+        Bound::Included(x) => Bound::Included((x.clone(), min)),
+        Bound::Excluded(x) => Bound::Excluded((x.clone(), max)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(x) => Bound::Included((x.clone(), max)),
+        Bound::Excluded(x) => Bound::Excluded((x.clone(), min)),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+    (start, end)
+}
+
+/// Evaluates the predicate of a `#[check]` constraint on a value.
+///
+/// The predicate is given as an argument so that the types of closure parameters are inferred from `T`. It can be
+/// any function or closure, including closures that capture values.
+pub fn check_constraint<T: ?Sized>(value: &T, predicate: impl FnOnce(&T) -> bool) -> bool {
+    predicate(value)
+}
+
 /// Operations for a specific entity type on a store.
-pub trait EntityStore<T: Entity>: ops::Index<T::Id, Output = T> + 'static {
+///
+/// Implemented by the store types generated by `store!` for each of their entities, so that generic code can work
+/// with any entity of a store (`S: EntityStore<T>`).
+pub trait EntityStore<T: Entity>: Store + ops::Index<T::Id, Output = T> {
+    /// Inserts an entity with a new key, checking all constraints.
+    ///
+    /// Entities with composite keys can only be inserted with `insert_with_id`.
     fn insert(&mut self, f: impl FnOnce(T::Id) -> T) -> Result<T::Id, Error>;
-    fn remove(&mut self, index: T::Id) -> Result<T, Error>;
+
+    /// Inserts an entity with the given key, checking all constraints.
+    fn insert_with_id(&mut self, id: T::Id, f: impl FnOnce(T::Id) -> T) -> Result<T::Id, Error>;
+
+    /// Returns all entities that removing the given entity would remove, checking the delete rules.
+    fn removal(&self, id: T::Id) -> Result<Self::Removal, Error>;
+
+    /// Removes an entity, applying the delete rules of the relationships that point to it.
+    ///
+    /// This only applies the delete rules of the store: use the `remove` method of the store trait to also apply
+    /// those of its extensions.
+    fn remove(&mut self, id: T::Id) -> Result<T, Error>;
+
+    fn get(&self, id: T::Id) -> Option<&T>;
+    fn contains(&self, id: T::Id) -> bool;
+
+    /// Returns the changes to the entities from `other` to `self`.
     fn delta<'a>(&'a self, other: &'a Self) -> impl Iterator<Item = Delta<&'a T>> + 'a;
-    fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T>;
-}*/
+
+    /// Iterates over the entities, in the order of the table.
+    fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T> + 'a;
+
+    /// Returns the number of entities.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Trait implemented by the store types generated by `store!`.
+pub trait Store: Clone + Default + 'static {
+    /// The set of entities removed by a removal operation on the store (e.g. `TrackDbRemoval`).
+    type Removal: Default;
+
+    /// Description of the entities and relationships of the store.
+    const SCHEMA: &'static StoreSchema;
+
+    /// Restores the entities of a previous version of the store (e.g. on `Database::rollback`).
+    ///
+    /// The timestamps of the change log keep increasing across the restoration.
+    fn restore(&mut self, prev: &Self);
+}
 
 /// Trait implemented by databases that hold a specific store type.
-pub trait HasStore<Store> {
-    fn store(&self) -> &Store;
-    fn store_mut(&mut self) -> &mut Store;
+pub trait HasStore<S: Store> {
+    fn store(&self) -> &S;
+    fn store_mut(&mut self) -> &mut S;
+
+    /// Called before entities are removed from the store, with all entities that are going to be removed.
+    ///
+    /// Databases that also hold extensions of the store forward this to them, so that the delete rules of the
+    /// extensions are checked. Returning an error cancels the removal.
+    fn before_remove(&self, _removing: &S::Removal) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Called after entities have been removed from the store.
+    fn after_remove(&mut self, _removed: &S::Removal) {}
+}
+
+/// Trait implemented by the stores that extend the entities of another store (`store Name : Base;`).
+pub trait StoreExtension<Base: Store>: Store {
+    /// Checks the delete rules of the extension before entities are removed from the base store.
+    ///
+    /// Returns the entities of the extension store that will be removed along with them.
+    fn before_base_remove(&self, removing: &Base::Removal) -> Result<Self::Removal, Error>;
+
+    /// Removes the data attached to entities that have been removed from the base store, and applies the delete
+    /// rules of the extension.
+    ///
+    /// Returns the entities of the extension store that have been removed along with them.
+    fn after_base_remove(&mut self, removed: &Base::Removal) -> Self::Removal;
 }
 
 pub trait Relation {
@@ -346,8 +456,14 @@ pub trait Trigger<DB: ?Sized, R: Relation> {
 
 /// Operations on a database type.
 pub trait Database: Send + 'static {
+    /// Saves the contents of all stores of the database, and returns the revision that identifies them.
+    fn snapshot(&mut self) -> RevIndex;
+
     /// Rolls back the database to the given revision.
-    fn rollback(&self, index: RevIndex);
+    ///
+    /// Snapshots taken after this revision are discarded. Returns `Error::RevisionNotFound` if the revision has been
+    /// discarded by a previous rollback, or was not taken on this database.
+    fn rollback(&mut self, index: RevIndex) -> Result<(), Error>;
 }
 
 
@@ -5,6 +5,15 @@
 #[repr(transparent)]
 pub struct TableIndex(u32);
 
+impl TableIndex {
+    pub const fn from_u32(x: u32) -> Self {
+        TableIndex(x)
+    }
+    pub const fn as_u32(self) -> u32 {
+        self.0
+    }
+}
+
 /// Identifies a value in a table.
 ///
 /// Internally it's just a newtype for a u32 index.
@@ -20,7 +20,74 @@ pub enum Error {
     #[error("the operation contains a reference to a non-existent entity")]
     ForeignKeyViolation,
 
+    /// The operation would give an attribute declared `#[unique]` the same value as in another entity.
+    #[error("the value of `{entity}.{attribute}` is not unique")]
+    UniqueViolation {
+        entity: &'static str,
+        attribute: &'static str,
+    },
+
+    /// The operation would result in an entity that doesn't satisfy a `#[check]` constraint.
+    ///
+    /// Constraints are named after the entity and the checked attribute (e.g. `Album.year`), or the name given to
+    /// the constraint.
+    #[error("the check constraint `{constraint}` is violated")]
+    CheckViolation { constraint: &'static str },
+
+    /// The operation would make an entity its own ancestor in a hierarchy (e.g. `rel parent: Node?.children`).
+    #[error("the operation would create a cycle in a hierarchy of entities")]
+    CycleDetected,
+
+    /// The position is out of the bounds of an ordered relationship (e.g. `rel tracks: Track[]`).
+    #[error("position {index} is out of bounds for a list of length {len}")]
+    PositionOutOfBounds { index: usize, len: usize },
+
+    /// The revision is not saved in the database: it was discarded by a rollback, or taken on another database.
+    #[error("the revision could not be found")]
+    RevisionNotFound,
+
     /// The entity could not be found.
     #[error("the entity could not be found")]
     EntityNotFound,
+
+    /// Data imported into a store has an attribute or key that is missing or has the wrong type.
+    #[error("invalid value for `{entity}.{attribute}`")]
+    InvalidValue {
+        entity: &'static str,
+        attribute: &'static str,
+    },
+
+    /// Data imported into a store has rows of an entity that is not in the schema of the store.
+    #[error("unknown entity `{entity}`")]
+    UnknownEntity { entity: String },
+
+    /// Data imported into a store has an attribute or relationship that is not in the schema of the store.
+    #[error("unknown attribute `{entity}.{attribute}`")]
+    UnknownAttribute { entity: &'static str, attribute: String },
+
+    /// No migration is registered to upgrade data from the given version of a schema.
+    #[error("no migration from version {version}")]
+    MissingMigration { version: u32 },
+
+    /// The data was saved with a version of the schema newer than the version of the store.
+    #[error("unsupported version {version}")]
+    UnsupportedVersion { version: u32 },
+
+    /// An entity with the same key already exists.
+    #[error("an entity with the same key already exists")]
+    DuplicateKey,
+
+    /// The entity created for a key has a different key, e.g. the closure given to `insert` ignored its argument.
+    #[error("the key of the entity does not match the key it was inserted with")]
+    KeyMismatch,
+
+    /// The key of the entity must be specified when inserting it.
+    ///
+    /// E.g. inserting an entity with a composite key without giving the key.
+    #[error("the key of the entity must be specified")]
+    KeyRequired,
+
+    /// All the ids of the entity have been used, e.g. after inserting an entity with the largest possible id.
+    #[error("no more ids are available for the entity")]
+    IdsExhausted,
 }
@@ -1,15 +1,20 @@
 #![feature(macro_metavar_expr)]
 pub mod db;
+mod changes;
 mod db_index;
 mod error;
 mod index_vec;
+pub mod migration;
+pub mod schema;
 mod table;
 mod circuit;
 
-pub use db::{ Database, Entity, HasStore, EntityId};
-pub use db_index::{DbIndex, Index};
+pub use changes::ChangeLog;
+pub use db::{ Database, Entity, EntityStore, HasStore, EntityId, EntityKey, Store, StoreExtension};
+pub use db_index::{DbIndex, Index, TableIndex};
 pub use error::Error;
-pub use table::{Delta, Table};
+pub use schema::StoreSchema;
+pub use table::{ClusteredTable, Delta, KeyedTable, ListDelta, ListTable, Table};
 
 #[doc(hidden)]
 pub use im;
@@ -0,0 +1,259 @@
+//! Schema versioning and data migrations.
+//!
+//! Stores declared with `#[version(N)]` can export their data to a type-erased representation (`StoreData`), and
+//! import it back. Data saved with an older version of the schema is upgraded on import by the migrations registered
+//! in `Migrations`, which work on the type-erased representation: they can rename attributes and entities, fill in
+//! default values, split entities or change the keys of relationships. Indices are then rebuilt from the migrated
+//! data, and all constraints are checked.
+use crate::Error;
+use std::collections::BTreeMap;
+
+/// A type-erased value of an attribute or a key.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// An absent optional value.
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    /// A sequence of values: `Vec`s, composite keys, and the destinations of to-many relationships.
+    List(Vec<Value>),
+    /// The key of an entity generated by the store.
+    Id(u32),
+    /// The key of a subtype of an abstract entity, tagged with the name of the subtype (e.g. `Circle`).
+    Tagged(String, Box<Value>),
+}
+
+/// A type-erased row: the values of the attributes and relationships of an entity, by name. The key of the entity is
+/// named `id`.
+pub type Row = BTreeMap<String, Value>;
+
+/// Type-erased data of a store, as exported by the `export` method of stores declared with `#[version(N)]`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct StoreData {
+    /// The version of the schema of the data.
+    pub version: u32,
+    /// The rows of each entity, by entity name.
+    pub entities: BTreeMap<String, Vec<Row>>,
+}
+
+impl StoreData {
+    pub fn new(version: u32) -> StoreData {
+        StoreData {
+            version,
+            entities: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the rows of an entity.
+    pub fn rows(&self, entity: &str) -> &[Row] {
+        self.entities.get(entity).map_or(&[], |rows| rows.as_slice())
+    }
+
+    /// Returns the rows of an entity, for modification. New entities have no rows.
+    pub fn rows_mut(&mut self, entity: &str) -> &mut Vec<Row> {
+        self.entities.entry(entity.to_string()).or_default()
+    }
+
+    /// Removes an entity and returns its rows (e.g. to split them into several entities).
+    pub fn remove_entity(&mut self, entity: &str) -> Vec<Row> {
+        self.entities.remove(entity).unwrap_or_default()
+    }
+
+    pub fn rename_entity(&mut self, from: &str, to: &str) {
+        let rows = self.remove_entity(from);
+        self.rows_mut(to).extend(rows);
+    }
+
+    /// Renames an attribute or relationship in all rows of an entity.
+    pub fn rename_attribute(&mut self, entity: &str, from: &str, to: &str) {
+        for row in self.rows_mut(entity) {
+            if let Some(value) = row.remove(from) {
+                row.insert(to.to_string(), value);
+            }
+        }
+    }
+
+    /// Removes an attribute or relationship from all rows of an entity.
+    pub fn remove_attribute(&mut self, entity: &str, attribute: &str) {
+        for row in self.rows_mut(entity) {
+            row.remove(attribute);
+        }
+    }
+
+    /// Gives a value to an attribute in the rows of an entity that don't have one (e.g. a new attribute).
+    pub fn fill_default(&mut self, entity: &str, attribute: &str, value: Value) {
+        for row in self.rows_mut(entity) {
+            row.entry(attribute.to_string()).or_insert_with(|| value.clone());
+        }
+    }
+
+    /// Replaces the values of an attribute or relationship in the rows of an entity that have one (e.g. to change the
+    /// keys of the destinations of a relationship).
+    pub fn map_values(&mut self, entity: &str, attribute: &str, mut f: impl FnMut(Value) -> Value) {
+        for row in self.rows_mut(entity) {
+            if let Some(value) = row.remove(attribute) {
+                row.insert(attribute.to_string(), f(value));
+            }
+        }
+    }
+}
+
+/// Conversion between the values of attributes and keys, and `Value`.
+///
+/// All attributes of a store declared with `#[version(N)]` must implement it. It is implemented by the generated key
+/// types.
+pub trait Field: Sized {
+    fn to_value(&self) -> Value;
+
+    /// Converts a value back, returning `None` if it has the wrong type.
+    fn from_value(value: Value) -> Option<Self>;
+}
+
+/// Returns the value of an attribute in a row, converted to the type of the attribute.
+///
+/// Attributes that are absent from the row are `Value::Null`.
+pub fn field<T: Field>(row: &Row, entity: &'static str, attribute: &'static str) -> Result<T, Error> {
+    let value = row.get(attribute).cloned().unwrap_or(Value::Null);
+    T::from_value(value).ok_or(Error::InvalidValue { entity, attribute })
+}
+
+macro_rules! impl_field {
+    ($variant:ident($repr:ty): $($t:ty),*) => {
+        $(
+            impl Field for $t {
+                fn to_value(&self) -> Value {
+                    Value::$variant(*self as $repr)
+                }
+
+                fn from_value(value: Value) -> Option<Self> {
+                    match value {
+                        Value::$variant(v) => <$t>::try_from(v).ok(),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_field!(Int(i64): i8, i16, i32, i64, isize);
+impl_field!(UInt(u64): u8, u16, u32, u64, usize);
+
+impl Field for bool {
+    fn to_value(&self) -> Value {
+        Value::Bool(*self)
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl Field for f32 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self as f64)
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Float(v) => Some(v as f32),
+            _ => None,
+        }
+    }
+}
+
+impl Field for f64 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self)
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Float(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl Field for String {
+    fn to_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::String(v) => Some(v),
+            _ => None,
+        }
+    }
+}
+
+impl<T: Field> Field for Option<T> {
+    fn to_value(&self) -> Value {
+        match self {
+            Some(v) => v.to_value(),
+            None => Value::Null,
+        }
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::Null => Some(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl<T: Field> Field for Vec<T> {
+    fn to_value(&self) -> Value {
+        Value::List(self.iter().map(Field::to_value).collect())
+    }
+
+    fn from_value(value: Value) -> Option<Self> {
+        match value {
+            Value::List(values) => values.into_iter().map(T::from_value).collect(),
+            _ => None,
+        }
+    }
+}
+
+type Migration = Box<dyn Fn(&mut StoreData) -> Result<(), Error>>;
+
+/// Upgrade functions between versions of a schema.
+#[derive(Default)]
+pub struct Migrations {
+    steps: BTreeMap<u32, Migration>,
+}
+
+impl Migrations {
+    pub fn new() -> Migrations {
+        Migrations::default()
+    }
+
+    /// Registers the function that upgrades data from `version` to `version + 1`.
+    pub fn add(&mut self, version: u32, f: impl Fn(&mut StoreData) -> Result<(), Error> + 'static) {
+        self.steps.insert(version, Box::new(f));
+    }
+
+    /// Upgrades data to the given version, applying the migrations from the version of the data in order.
+    pub fn migrate(&self, data: &mut StoreData, version: u32) -> Result<(), Error> {
+        if data.version > version {
+            return Err(Error::UnsupportedVersion { version: data.version });
+        }
+        while data.version < version {
+            let step = self
+                .steps
+                .get(&data.version)
+                .ok_or(Error::MissingMigration { version: data.version })?;
+            step(data)?;
+            data.version += 1;
+        }
+        Ok(())
+    }
+}
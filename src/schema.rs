@@ -0,0 +1,125 @@
+//! Descriptions of the schemas of stores, generated by `store!`.
+//!
+//! They allow tools that work on any store (inspectors, exporters, diagram generators...) to discover its entities,
+//! attributes and relationships at runtime.
+use crate::TableIndex;
+
+/// Description of a store (e.g. `TrackDbStore::SCHEMA`).
+#[derive(Debug)]
+pub struct StoreSchema {
+    /// The name of the store (e.g. `TrackDb`).
+    pub name: &'static str,
+    /// The version of the schema, for stores declared with `#[version(N)]`.
+    pub version: Option<u32>,
+    /// The schema of the extended store, for stores that extend another store.
+    pub base: Option<&'static StoreSchema>,
+    /// The entities of the store, in declaration order.
+    pub entities: &'static [EntitySchema],
+}
+
+impl StoreSchema {
+    /// Returns the entity with the given name.
+    pub fn entity(&self, name: &str) -> Option<&EntitySchema> {
+        self.entities.iter().find(|entity| entity.name == name)
+    }
+
+    /// Returns the entity stored in the table with the given index.
+    pub fn table(&self, table: TableIndex) -> Option<&EntitySchema> {
+        self.entities.iter().find(|entity| entity.table == Some(table))
+    }
+}
+
+/// Description of an entity.
+#[derive(Debug)]
+pub struct EntitySchema {
+    /// The name of the entity (e.g. `Track`, or `AlbumExt` for an extension).
+    pub name: &'static str,
+    /// Identifies the table of the entity among the tables of the store. The tables are numbered in declaration order,
+    /// skipping abstract entities and extensions, which have no table of their own.
+    pub table: Option<TableIndex>,
+    /// The names of the key types (e.g. `["TrackId"]`, or one per component of a composite key). Empty for
+    /// extensions, whose key is the key of the extended entity.
+    pub key: &'static [&'static str],
+    /// Whether the entity is abstract: it has no rows of its own, its entities are those of its subtypes.
+    pub is_abstract: bool,
+    /// The abstract entity that this entity is a subtype of.
+    pub supertype: Option<&'static str>,
+    /// For an extension, the extended entity of the base store.
+    pub extends: Option<&'static str>,
+    /// The attributes of the entity, including those inherited from the supertype.
+    pub attributes: &'static [AttributeSchema],
+    /// The relationships of the entity, including those inherited from the supertype.
+    pub relations: &'static [RelationSchema],
+}
+
+impl EntitySchema {
+    /// Returns the attribute with the given name.
+    pub fn attribute(&self, name: &str) -> Option<&AttributeSchema> {
+        self.attributes.iter().find(|attr| attr.name == name)
+    }
+
+    /// Returns the relationship with the given name.
+    pub fn relation(&self, name: &str) -> Option<&RelationSchema> {
+        self.relations.iter().find(|rel| rel.name == name)
+    }
+}
+
+/// Description of an attribute.
+#[derive(Debug)]
+pub struct AttributeSchema {
+    /// The name of the attribute.
+    pub name: &'static str,
+    /// The type of the attribute, as written in the schema (e.g. `String`).
+    pub type_name: &'static str,
+    /// Whether the attribute is optional (`comment: String?`).
+    pub optional: bool,
+    /// Whether the value must be unique among all entities (`#[unique]`).
+    pub unique: bool,
+    /// Whether the attribute has an index (`#[index]`).
+    pub indexed: bool,
+    /// Whether the value is fixed when the entity is inserted (`#[readonly]`).
+    pub readonly: bool,
+}
+
+/// Number of destinations of a relationship.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Multiplicity {
+    /// At most one destination (`Album?`).
+    ZeroOrOne,
+    /// Exactly one destination (`Album`).
+    One,
+    /// Any number of destinations (`Track*`, or `Track[]` for ordered relationships).
+    Many,
+}
+
+/// What happens to the sources of a relationship when its destination is deleted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DeleteRule {
+    /// The deletion fails if the destination is referenced.
+    Deny,
+    /// The reference is removed.
+    Nullify,
+    /// The sources are deleted as well.
+    Cascade,
+}
+
+/// Description of a relationship.
+#[derive(Debug)]
+pub struct RelationSchema {
+    /// The name of the relationship.
+    pub name: &'static str,
+    /// The name of the destination entity.
+    pub destination: &'static str,
+    pub multiplicity: Multiplicity,
+    /// Whether the destinations are ordered, with duplicates allowed (`Track[]`).
+    pub ordered: bool,
+    /// Whether there can be at most one source for each destination (`unique`).
+    pub unique: bool,
+    /// The name of the inverse relationship in the destination entity, if any.
+    pub inverse: Option<&'static str>,
+    /// The delete rule applied to the sources when a destination is deleted.
+    pub delete_rule: DeleteRule,
+    /// Whether the relationship is stored in the store. Relationships that are not stored are the inverse side of
+    /// another relationship, and are navigated with its index.
+    pub stored: bool,
+}
@@ -1,7 +1,7 @@
 use crate::db::EntityId;
 use crate::Entity;
 use im::ordmap::{DiffItem, OrdMap};
-use std::ops::{Index, IndexMut};
+use std::ops::{Index, IndexMut, RangeBounds};
 
 #[derive(Clone)]
 struct Row<T> {
@@ -9,12 +9,28 @@ struct Row<T> {
     revision: u32,
 }
 
+/// Rows are compared by revision: two rows are equal if one is a copy of the other taken from a previous version of
+/// the table, and the row was not modified since then.
 impl<T> PartialEq for Row<T> {
     fn eq(&self, other: &Self) -> bool {
         self.revision == other.revision
     }
 }
 
+/// Generates the revisions of the rows of a table.
+///
+/// Each inserted or modified row gets a revision greater than those of all the previous rows, so that a row that is
+/// removed and replaced by another one with the same key is not equal to it.
+#[derive(Clone, Default)]
+struct Revisions(u32);
+
+impl Revisions {
+    fn next_revision(&mut self) -> u32 {
+        self.0 += 1;
+        self.0
+    }
+}
+
 type Map<T: Entity> = OrdMap<u32, Row<T>>;
 
 #[derive(Clone, Debug)]
@@ -24,29 +40,49 @@ pub enum Delta<V> {
     Update { old: V, new: V },
 }
 
-/// Stores entity data.
+/// Stores entity data, keyed by ids generated when inserting entities.
 #[derive(Clone)]
 pub struct Table<T: Entity> {
     pub(crate) data: Map<T>,
     next_id: u32,
+    revisions: Revisions,
 }
 
-impl<T: Entity> Table<T> {
+impl<T: Entity> Table<T>
+where
+    T::Id: EntityId,
+{
     pub fn new() -> Table<T> {
         Table {
             data: OrdMap::new(),
             next_id: 0,
+            revisions: Revisions::default(),
         }
     }
 
     pub fn insert_at(&mut self, data: T) -> T::Id {
-        assert_eq!(data.id(), self.next_id());
+        assert_eq!(Some(data.id()), self.next_id());
         let id = data.id();
         self.next_id += 1;
-        self.data.insert(id.to_u32(), Row { data, revision: 0 });
+        let revision = self.revisions.next_revision();
+        self.data.insert(id.to_u32(), Row { data, revision });
         id
     }
 
+    /// Inserts an entity with a specific id. Returns `false` if an entity with the same id already exists.
+    ///
+    /// Ids generated after that are greater than the id of the entity.
+    pub fn insert_with_id(&mut self, data: T) -> bool {
+        let id = data.id().to_u32();
+        if self.data.contains_key(&id) {
+            return false;
+        }
+        self.next_id = self.next_id.max(id.saturating_add(1));
+        let revision = self.revisions.next_revision();
+        self.data.insert(id, Row { data, revision });
+        true
+    }
+
     pub fn remove(&mut self, id: T::Id) -> Option<T> {
         self.data.remove(&id.to_u32()).map(|row| row.data)
     }
@@ -57,7 +93,7 @@ impl<T: Entity> Table<T> {
 
     pub fn get_mut(&mut self, id: T::Id) -> Option<&mut T> {
         if let Some(row) = self.data.get_mut(&id.to_u32()) {
-            row.revision += 1;
+            row.revision = self.revisions.next_revision();
             Some(&mut row.data)
         } else {
             None
@@ -92,8 +128,10 @@ impl<T: Entity> Table<T> {
         self.data.values().map(|row| &row.data)
     }
 
-    pub fn next_id(&self) -> T::Id {
-        T::Id::from_u32(self.next_id)
+    /// Returns the id of the next inserted entity, or `None` if all ids have been used.
+    pub fn next_id(&self) -> Option<T::Id> {
+        // `u32::MAX` itself is not generated: it is past the largest id
+        (self.next_id < u32::MAX).then(|| T::Id::from_u32(self.next_id))
     }
 
     pub fn delta<'a>(&'a self, prev: &'a Table<T>) -> impl Iterator<Item = Delta<&'a T>> + 'a {
@@ -108,20 +146,538 @@ impl<T: Entity> Table<T> {
     }
 }
 
-impl<T: Entity> Index<T::Id> for Table<T> {
+impl<T: Entity> Index<T::Id> for Table<T>
+where
+    T::Id: EntityId,
+{
     type Output = T;
     fn index(&self, id: T::Id) -> &Self::Output {
         &self.data[&id.to_u32()].data
     }
 }
 
-impl<T: Entity> IndexMut<T::Id> for Table<T> {
+impl<T: Entity> IndexMut<T::Id> for Table<T>
+where
+    T::Id: EntityId,
+{
+    fn index_mut(&mut self, id: T::Id) -> &mut Self::Output {
+        self.get_mut(id).unwrap()
+    }
+}
+
+impl<T: Entity> Default for Table<T>
+where
+    T::Id: EntityId,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stores entity data, keyed by composite keys chosen when inserting entities.
+#[derive(Clone)]
+pub struct KeyedTable<T: Entity> {
+    data: OrdMap<T::Id, Row<T>>,
+    revisions: Revisions,
+}
+
+impl<T: Entity> KeyedTable<T> {
+    pub fn new() -> KeyedTable<T> {
+        KeyedTable {
+            data: OrdMap::new(),
+            revisions: Revisions::default(),
+        }
+    }
+
+    /// Inserts an entity. Returns `false` if an entity with the same key already exists.
+    pub fn insert(&mut self, data: T) -> bool {
+        let id = data.id();
+        if self.data.contains_key(&id) {
+            return false;
+        }
+        let revision = self.revisions.next_revision();
+        self.data.insert(id, Row { data, revision });
+        true
+    }
+
+    pub fn remove(&mut self, id: T::Id) -> Option<T> {
+        self.data.remove(&id).map(|row| row.data)
+    }
+
+    pub fn get(&self, id: T::Id) -> Option<&T> {
+        self.data.get(&id).map(|row| &row.data)
+    }
+
+    pub fn get_mut(&mut self, id: T::Id) -> Option<&mut T> {
+        if let Some(row) = self.data.get_mut(&id) {
+            row.revision = self.revisions.next_revision();
+            Some(&mut row.data)
+        } else {
+            None
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.data.values().map(|row| &row.data)
+    }
+
+    /// Returns the entities whose keys are in the given range, in key order.
+    pub fn range(&self, range: impl RangeBounds<T::Id>) -> impl Iterator<Item = &T> {
+        self.data.range(range).map(|(_, row)| &row.data)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    pub fn contains(&self, id: T::Id) -> bool {
+        self.data.contains_key(&id)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = T::Id> + '_ {
+        self.data.keys().copied()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.data.values().map(|row| &row.data)
+    }
+
+    pub fn delta<'a>(&'a self, prev: &'a KeyedTable<T>) -> impl Iterator<Item = Delta<&'a T>> + 'a {
+        prev.data.diff(&self.data).map(|item| match item {
+            DiffItem::Add(_, v) => Delta::Insert(&v.data),
+            DiffItem::Update { old, new } => Delta::Update {
+                old: &old.1.data,
+                new: &new.1.data,
+            },
+            DiffItem::Remove(_, v) => Delta::Remove(&v.data),
+        })
+    }
+}
+
+impl<T: Entity> Index<T::Id> for KeyedTable<T> {
+    type Output = T;
+    fn index(&self, id: T::Id) -> &Self::Output {
+        &self.data[&id].data
+    }
+}
+
+impl<T: Entity> IndexMut<T::Id> for KeyedTable<T> {
+    fn index_mut(&mut self, id: T::Id) -> &mut Self::Output {
+        self.get_mut(id).unwrap()
+    }
+}
+
+impl<T: Entity> Default for KeyedTable<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stores entity data in the order of a cluster key (e.g. `(AlbumId, TrackId)` to store tracks grouped by album),
+/// with ids generated when inserting entities.
+///
+/// The cluster key must be unique, which is usually ensured by including the id of the entity.
+#[derive(Clone)]
+pub struct ClusteredTable<T: Entity, K> {
+    data: OrdMap<K, Row<T>>,
+    /// Cluster key of each entity.
+    pk: OrdMap<T::Id, K>,
+    next_id: u32,
+    revisions: Revisions,
+}
+
+impl<T: Entity, K: Ord + Clone> ClusteredTable<T, K>
+where
+    T::Id: EntityId,
+{
+    pub fn new() -> ClusteredTable<T, K> {
+        ClusteredTable {
+            data: OrdMap::new(),
+            pk: OrdMap::new(),
+            next_id: 0,
+            revisions: Revisions::default(),
+        }
+    }
+
+    /// Inserts an entity with a specific id. Returns `false` if an entity with the same id already exists.
+    ///
+    /// Ids generated after that are greater than the id of the entity.
+    pub fn insert_with_id(&mut self, key: K, data: T) -> bool {
+        let id = data.id();
+        if self.pk.contains_key(&id) {
+            return false;
+        }
+        self.next_id = self.next_id.max(id.to_u32().saturating_add(1));
+        self.pk.insert(id, key.clone());
+        let revision = self.revisions.next_revision();
+        self.data.insert(key, Row { data, revision });
+        true
+    }
+
+    /// Moves an entity after the components of its cluster key have been modified.
+    pub fn recluster(&mut self, id: T::Id, key: K) {
+        let prev = self.pk.insert(id, key.clone()).unwrap();
+        if prev != key {
+            let row = self.data.remove(&prev).unwrap();
+            self.data.insert(key, row);
+        }
+    }
+
+    pub fn remove(&mut self, id: T::Id) -> Option<T> {
+        let key = self.pk.remove(&id)?;
+        self.data.remove(&key).map(|row| row.data)
+    }
+
+    pub fn get(&self, id: T::Id) -> Option<&T> {
+        let key = self.pk.get(&id)?;
+        self.data.get(key).map(|row| &row.data)
+    }
+
+    pub fn get_mut(&mut self, id: T::Id) -> Option<&mut T> {
+        let key = self.pk.get(&id)?;
+        if let Some(row) = self.data.get_mut(key) {
+            row.revision = self.revisions.next_revision();
+            Some(&mut row.data)
+        } else {
+            None
+        }
+    }
+
+    /// Iterates over the entities in cluster key order.
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.data.values().map(|row| &row.data)
+    }
+
+    /// Returns the entities whose cluster keys are in the given range, in cluster key order.
+    pub fn range(&self, range: impl RangeBounds<K>) -> impl Iterator<Item = &T> {
+        self.data.range(range).map(|(_, row)| &row.data)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+        self.pk.clear();
+    }
+
+    pub fn contains(&self, id: T::Id) -> bool {
+        self.pk.contains_key(&id)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = T::Id> + '_ {
+        self.pk.keys().copied()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &T> {
+        self.data.values().map(|row| &row.data)
+    }
+
+    /// Returns the id of the next inserted entity, or `None` if all ids have been used.
+    pub fn next_id(&self) -> Option<T::Id> {
+        // `u32::MAX` itself is not generated: it is past the largest id
+        (self.next_id < u32::MAX).then(|| T::Id::from_u32(self.next_id))
+    }
+
+    /// Returns the changes since `prev`.
+    ///
+    /// An entity that was moved to another cluster is reported as updated.
+    pub fn delta<'a>(&'a self, prev: &'a ClusteredTable<T, K>) -> impl Iterator<Item = Delta<&'a T>> + 'a {
+        prev.data.diff(&self.data).filter_map(move |item| match item {
+            DiffItem::Add(_, v) => Some(match prev.get(v.data.id()) {
+                Some(old) => Delta::Update { old, new: &v.data },
+                None => Delta::Insert(&v.data),
+            }),
+            DiffItem::Update { old, new } => Some(Delta::Update {
+                old: &old.1.data,
+                new: &new.1.data,
+            }),
+            // reported with the addition if the entity was moved
+            DiffItem::Remove(_, v) if self.contains(v.data.id()) => None,
+            DiffItem::Remove(_, v) => Some(Delta::Remove(&v.data)),
+        })
+    }
+}
+
+impl<T: Entity, K: Ord + Clone> Index<T::Id> for ClusteredTable<T, K>
+where
+    T::Id: EntityId,
+{
+    type Output = T;
+    fn index(&self, id: T::Id) -> &Self::Output {
+        self.get(id).unwrap()
+    }
+}
+
+impl<T: Entity, K: Ord + Clone> IndexMut<T::Id> for ClusteredTable<T, K>
+where
+    T::Id: EntityId,
+{
     fn index_mut(&mut self, id: T::Id) -> &mut Self::Output {
         self.get_mut(id).unwrap()
     }
 }
 
-impl<T: Entity> Default for Table<T> {
+impl<T: Entity, K: Ord + Clone> Default for ClusteredTable<T, K>
+where
+    T::Id: EntityId,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Position of an element in an ordered list, as the digits of a fraction in `[0, 1)`.
+///
+/// A position can always be generated between two others, so inserting or moving an element never changes the
+/// positions of the other elements. The last digit is never zero, so that positions compare like the fractions.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+struct Position(Vec<u8>);
+
+impl Position {
+    /// Returns a position strictly between `prev` (or the start of the list) and `next` (or the end of the list).
+    fn between(prev: Option<&Position>, next: Option<&Position>) -> Position {
+        let prev = prev.map_or(&[][..], |p| &p.0[..]);
+        let mut next = next.map(|p| &p.0[..]);
+        let mut digits = vec![];
+        for i in 0.. {
+            let lo = prev.get(i).copied().unwrap_or(0) as u16;
+            let hi = next.map_or(256, |next| next.get(i).copied().unwrap_or(0) as u16);
+            if hi - lo > 1 {
+                digits.push(((lo + hi) / 2) as u8);
+                break;
+            }
+            digits.push(lo as u8);
+            if hi > lo {
+                // the position is now less than `next` whatever the following digits
+                next = None;
+            }
+        }
+        Position(digits)
+    }
+}
+
+/// A change in the ordered lists of a relationship, returned by [`ListTable::delta`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ListDelta<S, D> {
+    /// `target` was inserted at `index` in the list of `source`.
+    Insert { source: S, target: D, index: usize },
+    /// `target` was removed from `index` in the previous list of `source`.
+    Remove { source: S, target: D, index: usize },
+    /// `target` was moved from `from` in the previous list of `source` to `to` in the current list.
+    Move { source: S, target: D, from: usize, to: usize },
+}
+
+/// Stores the ordered lists of targets of a to-many relationship (e.g. the tracks of playlists), where a target can
+/// appear several times in a list.
+#[derive(Clone)]
+pub struct ListTable<S, D> {
+    lists: OrdMap<S, OrdMap<Position, D>>,
+    /// Number of occurrences of each target, by list.
+    counts: OrdMap<D, OrdMap<S, usize>>,
+}
+
+impl<S: Ord + Clone, D: Ord + Clone> ListTable<S, D> {
+    pub fn new() -> ListTable<S, D> {
+        ListTable {
+            lists: OrdMap::new(),
+            counts: OrdMap::new(),
+        }
+    }
+
+    /// Returns the targets in the list of `source`, in order.
+    pub fn iter(&self, source: &S) -> impl Iterator<Item = &D> + '_ {
+        self.lists.get(source).into_iter().flat_map(|list| list.values())
+    }
+
+    /// Returns the length of the list of `source`.
+    pub fn len(&self, source: &S) -> usize {
+        self.lists.get(source).map_or(0, |list| list.len())
+    }
+
+    /// Returns the target at `index` in the list of `source`.
+    pub fn get(&self, source: &S, index: usize) -> Option<&D> {
+        self.iter(source).nth(index)
+    }
+
+    /// Returns the sources whose list contains `target`.
+    pub fn sources(&self, target: &D) -> impl Iterator<Item = &S> + '_ {
+        self.counts.get(target).into_iter().flat_map(|counts| counts.keys())
+    }
+
+    /// Inserts `target` at `index` in the list of `source`. Returns `false` if `index` is greater than the length of
+    /// the list.
+    pub fn insert_at(&mut self, source: S, index: usize, target: D) -> bool {
+        let list = self.lists.entry(source.clone()).or_default();
+        if index > list.len() {
+            if list.is_empty() {
+                self.lists.remove(&source);
+            }
+            return false;
+        }
+        let position = {
+            let mut positions = list.keys().skip(index.saturating_sub(1));
+            let prev = if index > 0 { positions.next() } else { None };
+            Position::between(prev, positions.next())
+        };
+        list.insert(position, target.clone());
+        *self.counts.entry(target).or_default().entry(source).or_default() += 1;
+        true
+    }
+
+    /// Removes the target at `index` in the list of `source`.
+    pub fn remove_at(&mut self, source: &S, index: usize) -> Option<D> {
+        let list = self.lists.get_mut(source)?;
+        let position = list.keys().nth(index)?.clone();
+        let target = list.remove(&position)?;
+        if list.is_empty() {
+            self.lists.remove(source);
+        }
+        self.decrement(&target, source, 1);
+        Some(target)
+    }
+
+    /// Moves the target at `from` to `to` in the list of `source`, where `to` is the index in the list after the
+    /// move. Returns `false` if either index is out of bounds.
+    pub fn move_to(&mut self, source: &S, from: usize, to: usize) -> bool {
+        let Some(list) = self.lists.get_mut(source) else {
+            return false;
+        };
+        if from >= list.len() || to >= list.len() {
+            return false;
+        }
+        if from == to {
+            return true;
+        }
+        let (position, target) = list.iter().nth(from).map(|(p, t)| (p.clone(), t.clone())).unwrap();
+        list.remove(&position);
+        let position = {
+            let mut positions = list.keys().skip(to.saturating_sub(1));
+            let prev = if to > 0 { positions.next() } else { None };
+            Position::between(prev, positions.next())
+        };
+        list.insert(position, target);
+        true
+    }
+
+    /// Removes the list of `source`.
+    pub fn remove_source(&mut self, source: &S) {
+        if let Some(list) = self.lists.remove(source) {
+            for target in list.values() {
+                self.decrement(target, source, 1);
+            }
+        }
+    }
+
+    /// Removes all occurrences of `target` from the lists.
+    pub fn remove_target(&mut self, target: &D) {
+        let Some(counts) = self.counts.remove(target) else {
+            return;
+        };
+        for source in counts.keys() {
+            let list = self.lists.get_mut(source).unwrap();
+            *list = list.iter().filter(|(_, t)| *t != target).map(|(p, t)| (p.clone(), t.clone())).collect();
+            if list.is_empty() {
+                self.lists.remove(source);
+            }
+        }
+    }
+
+    fn decrement(&mut self, target: &D, source: &S, n: usize) {
+        let counts = self.counts.get_mut(target).unwrap();
+        let count = counts.get_mut(source).unwrap();
+        *count -= n;
+        if *count == 0 {
+            counts.remove(source);
+            if counts.is_empty() {
+                self.counts.remove(target);
+            }
+        }
+    }
+
+    /// Returns the changes since `prev`.
+    ///
+    /// For each list, removals are reported first, then moves, then insertions. A target that was removed and
+    /// inserted again in the same list is reported as moved.
+    pub fn delta(&self, prev: &ListTable<S, D>) -> impl Iterator<Item = ListDelta<S, D>> {
+        let mut delta = vec![];
+        for item in prev.lists.diff(&self.lists) {
+            match item {
+                DiffItem::Add(source, list) => {
+                    delta.extend(list.values().enumerate().map(|(index, target)| ListDelta::Insert {
+                        source: source.clone(),
+                        target: target.clone(),
+                        index,
+                    }));
+                }
+                DiffItem::Remove(source, list) => {
+                    delta.extend(list.values().enumerate().map(|(index, target)| ListDelta::Remove {
+                        source: source.clone(),
+                        target: target.clone(),
+                        index,
+                    }));
+                }
+                DiffItem::Update { old: (source, old), new: (_, new) } => {
+                    let index_in = |list: &OrdMap<Position, D>, position: &Position| list.range(..position).count();
+                    let mut removed = vec![];
+                    let mut added = vec![];
+                    for item in old.diff(new) {
+                        match item {
+                            DiffItem::Add(position, target) => added.push((position, target)),
+                            DiffItem::Remove(position, target) => removed.push(Some((position, target))),
+                            DiffItem::Update { old: (position, old_target), new: (_, new_target) } => {
+                                removed.push(Some((position, old_target)));
+                                added.push((position, new_target));
+                            }
+                        }
+                    }
+                    let mut moves = vec![];
+                    let mut inserts = vec![];
+                    for (position, target) in added {
+                        let to = index_in(new, position);
+                        let moved = removed.iter_mut().find(|r| matches!(r, Some((_, t)) if *t == target));
+                        match moved.and_then(Option::take) {
+                            Some((old_position, _)) => moves.push(ListDelta::Move {
+                                source: source.clone(),
+                                target: target.clone(),
+                                from: index_in(old, old_position),
+                                to,
+                            }),
+                            None => inserts.push(ListDelta::Insert {
+                                source: source.clone(),
+                                target: target.clone(),
+                                index: to,
+                            }),
+                        }
+                    }
+                    delta.extend(removed.into_iter().flatten().map(|(position, target)| ListDelta::Remove {
+                        source: source.clone(),
+                        target: target.clone(),
+                        index: index_in(old, position),
+                    }));
+                    delta.extend(moves);
+                    delta.extend(inserts);
+                }
+            }
+        }
+        delta.into_iter()
+    }
+}
+
+impl<S: Ord + Clone, D: Ord + Clone> Default for ListTable<S, D> {
     fn default() -> Self {
         Self::new()
     }